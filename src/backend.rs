@@ -0,0 +1,87 @@
+mod fs;
+mod memory;
+mod read_only;
+mod replayed;
+
+pub use fs::FsBackend;
+pub use memory::MemoryBackend;
+pub(crate) use read_only::ReadOnlyBackend;
+pub(crate) use replayed::ReplayedBackend;
+
+use super::{
+    error::Result,
+    lock::{Lock, LockMode},
+    Durability,
+};
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// The storage a store reads and writes its files through.
+///
+/// Paths are passed exactly as the store builds them, so a backend decides
+/// for itself how they map onto the underlying storage.
+pub trait Backend: Debug + Send + Sync
+{
+    /// Read the contents of a file.
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Replace the contents of a file, so that readers either observe the old
+    /// or the new contents but never a mix of both.
+    fn write_atomic(&self, path: &Path, bytes: &[u8], durability: Durability) -> Result<()>;
+
+    /// Atomically create a file, failing if it already exists.
+    fn write_new(&self, path: &Path, bytes: &[u8], durability: Durability) -> Result<()>;
+
+    /// Remove a file.
+    fn remove(&self, path: &Path) -> Result<()>;
+
+    /// List the paths directly inside a directory.
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>>;
+
+    /// Check whether a file or directory exists.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Get the metadata of a file or directory.
+    fn metadata(&self, path: &Path) -> Result<Metadata>;
+
+    /// Create a directory and all of its parents.
+    fn create_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// Remove a directory and everything inside it.
+    fn remove_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// Try to take an advisory lock on a lock file.
+    ///
+    /// An exclusive lock creates the lock file and its parent directories if
+    /// needed. A shared lock never creates anything, and is taken right away
+    /// if the lock file does not exist, since no one can hold it exclusively.
+    ///
+    /// Returns `None` without waiting if someone else holds a conflicting
+    /// lock. The lock must be released when the returned [`Lock`] is dropped,
+    /// and when the process holding it exits.
+    fn try_lock(&self, path: &Path, mode: LockMode) -> Result<Option<Lock>>;
+}
+
+/// Metadata of a file or directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata
+{
+    /// Whether the path is a directory.
+    pub is_dir: bool,
+    /// The size of the file in bytes.
+    pub len: u64,
+    /// When the file was last modified.
+    pub modified: SystemTime,
+}
+
+impl Metadata
+{
+    /// Check whether the path is a regular file.
+    pub fn is_file(&self) -> bool
+    {
+        !self.is_dir
+    }
+}
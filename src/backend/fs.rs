@@ -0,0 +1,310 @@
+use super::{Backend, Metadata};
+use crate::{
+    error::{Operation, Result, StoreError},
+    Durability, Lock, LockMode,
+};
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+/// A backend storing files on the local filesystem.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FsBackend;
+
+impl Backend for FsBackend
+{
+    fn read(&self, path: &Path) -> Result<Vec<u8>>
+    {
+        fs::read(path).map_err(|error| StoreError::io(path, Operation::Read, error))
+    }
+
+    fn write_atomic(&self, path: &Path, bytes: &[u8], durability: Durability) -> Result<()>
+    {
+        write_atomic(path, bytes, durability)
+    }
+
+    fn write_new(&self, path: &Path, bytes: &[u8], durability: Durability) -> Result<()>
+    {
+        write_new(path, bytes, durability)
+    }
+
+    fn remove(&self, path: &Path) -> Result<()>
+    {
+        fs::remove_file(path).map_err(|error| StoreError::io(path, Operation::Remove, error))
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>>
+    {
+        fs::read_dir(dir)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect()
+            })
+            .map_err(|error| StoreError::io(dir, Operation::Directory, error))
+    }
+
+    fn exists(&self, path: &Path) -> bool
+    {
+        path.exists()
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata>
+    {
+        let metadata =
+            fs::metadata(path).map_err(|error| StoreError::io(path, Operation::Read, error))?;
+
+        Ok(Metadata {
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        })
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()>
+    {
+        fs::create_dir_all(path).map_err(|error| StoreError::io(path, Operation::Directory, error))
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()>
+    {
+        fs::remove_dir_all(path).map_err(|error| StoreError::io(path, Operation::Remove, error))
+    }
+
+    fn try_lock(&self, path: &Path, mode: LockMode) -> Result<Option<Lock>>
+    {
+        let opened = match mode
+        {
+            LockMode::Shared => File::open(path),
+            LockMode::Exclusive =>
+            {
+                if let Some(parent) = path
+                    .parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                {
+                    self.create_dir_all(parent)?;
+                }
+
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(path)
+            }
+        };
+
+        let file = match opened
+        {
+            Ok(file) => file,
+            // No one ever locked the file exclusively, so there is no writer
+            // to keep out.
+            Err(error) if mode == LockMode::Shared && error.kind() == ErrorKind::NotFound =>
+            {
+                return Ok(Some(Lock::new(path.to_path_buf(), mode, ())));
+            }
+            Err(error) => return Err(StoreError::io(path, Operation::Lock, error)),
+        };
+
+        let locked = match mode
+        {
+            LockMode::Shared => file.try_lock_shared(),
+            LockMode::Exclusive => file.try_lock(),
+        };
+
+        // The lock belongs to the open file, so the operating system releases
+        // it when the file is closed or the process exits.
+        match locked
+        {
+            Ok(()) => Ok(Some(Lock::new(path.to_path_buf(), mode, file))),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(error)) => Err(StoreError::io(path, Operation::Lock, error)),
+        }
+    }
+}
+
+/// Counter used to give every temporary file in this process a unique name.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Atomically replace the file at `path` with `bytes`.
+fn write_atomic(path: &Path, bytes: &[u8], durability: Durability) -> Result<()>
+{
+    let temp = write_temp(path, bytes, durability)?;
+
+    if let Err(error) = fs::rename(&temp, path)
+    {
+        let _ = fs::remove_file(&temp);
+        return Err(StoreError::io(path, Operation::Rename, error));
+    }
+
+    sync_parent(path, durability)
+}
+
+/// Atomically create the file at `path` with `bytes`, failing if it exists.
+fn write_new(path: &Path, bytes: &[u8], durability: Durability) -> Result<()>
+{
+    let temp = write_temp(path, bytes, durability)?;
+    let linked = fs::hard_link(&temp, path);
+
+    let _ = fs::remove_file(&temp);
+    linked.map_err(|error| StoreError::io(path, Operation::Rename, error))?;
+
+    sync_parent(path, durability)
+}
+
+/// Write `bytes` to a fresh temporary file next to `path`.
+fn write_temp(path: &Path, bytes: &[u8], durability: Durability) -> Result<PathBuf>
+{
+    let temp = temp_path(path);
+
+    if let Err(error) = write_file(&temp, bytes, durability)
+    {
+        let _ = fs::remove_file(&temp);
+        return Err(error);
+    }
+
+    Ok(temp)
+}
+
+/// Write `bytes` to a new file at `path` and flush it as requested.
+fn write_file(path: &Path, bytes: &[u8], durability: Durability) -> Result<()>
+{
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|error| StoreError::io(path, Operation::Write, error))?;
+
+    file.write_all(bytes)
+        .map_err(|error| StoreError::io(path, Operation::Write, error))?;
+
+    match durability
+    {
+        Durability::None => Ok(()),
+        Durability::Flush => file.sync_data(),
+        Durability::Full => file.sync_all(),
+    }
+    .map_err(|error| StoreError::io(path, Operation::Sync, error))
+}
+
+/// Get a unique temporary path in the same directory as `path`.
+fn temp_path(path: &Path) -> PathBuf
+{
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    path.with_file_name(format!(".{}.{}.{}.tmp", name, process::id(), counter))
+}
+
+/// Flush the directory containing `path` when full durability is requested.
+fn sync_parent(path: &Path, durability: Durability) -> Result<()>
+{
+    if durability != Durability::Full
+    {
+        return Ok(());
+    }
+
+    let parent = match path.parent()
+    {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    // Directories cannot be opened as files on every platform, in which case
+    // the rename is as durable as the platform allows.
+    if let Ok(dir) = File::open(parent)
+    {
+        dir.sync_all()
+            .map_err(|error| StoreError::io(parent, Operation::Sync, error))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::{env, process};
+
+    /// A directory under the system's temporary directory, removed when
+    /// dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir
+    {
+        /// Create a directory unique to this test and process.
+        fn new(name: &str) -> Self
+        {
+            let path = env::temp_dir().join(format!("store-{}-{}", name, process::id()));
+
+            fs::create_dir_all(&path).unwrap();
+
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir
+    {
+        fn drop(&mut self)
+        {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Test that atomic writes replace the file without leaving temporary files behind.
+    #[test]
+    fn test_write_atomic() -> Result<()>
+    {
+        let dir = TempDir::new("write-atomic");
+        let path = dir.0.join("value.json");
+
+        for durability in [Durability::None, Durability::Flush, Durability::Full]
+        {
+            FsBackend.write_atomic(&path, b"\"first\"", durability)?;
+            FsBackend.write_atomic(&path, b"\"second\"", durability)?;
+
+            assert_eq!(fs::read(&path).unwrap(), b"\"second\"");
+        }
+
+        assert!(matches!(
+            FsBackend.write_new(&path, b"\"third\"", Durability::Full),
+            Err(StoreError::AlreadyExists { .. })
+        ));
+        assert_eq!(fs::read(&path).unwrap(), b"\"second\"");
+        assert_eq!(FsBackend.list(&dir.0)?, vec![path]);
+
+        Ok(())
+    }
+
+    /// Test that lock files conflict between separate opens of the same file.
+    #[test]
+    fn test_try_lock() -> Result<()>
+    {
+        let dir = TempDir::new("try-lock");
+        let path = dir.0.join("nested/value.lock");
+
+        assert!(FsBackend.try_lock(&path, LockMode::Shared)?.is_some());
+        assert!(!dir.0.join("nested").exists());
+
+        let exclusive = FsBackend.try_lock(&path, LockMode::Exclusive)?;
+
+        assert!(exclusive.is_some());
+        assert!(FsBackend.try_lock(&path, LockMode::Shared)?.is_none());
+
+        drop(exclusive);
+
+        let shared = FsBackend.try_lock(&path, LockMode::Shared)?;
+
+        assert!(shared.is_some());
+        assert!(FsBackend.try_lock(&path, LockMode::Shared)?.is_some());
+        assert!(FsBackend.try_lock(&path, LockMode::Exclusive)?.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use super::{Backend, Metadata};
+use crate::{
+    error::{Operation, Result, StoreError},
+    lock, Durability, Lock, LockMode,
+};
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+/// A node in the in-memory file tree.
+#[derive(Debug, Clone)]
+enum Node
+{
+    /// A file with its contents.
+    File
+    {
+        bytes: Vec<u8>,
+        modified: SystemTime,
+    },
+    /// A directory.
+    Dir,
+}
+
+/// The holders of a lock.
+#[derive(Debug, Default)]
+struct Holders
+{
+    /// The number of shared holders.
+    shared: usize,
+    /// Whether the lock is held exclusively.
+    exclusive: bool,
+}
+
+/// The held locks, keyed by the path of their lock file.
+type Locks = Arc<Mutex<BTreeMap<PathBuf, Holders>>>;
+
+/// A lock held on an in-memory backend, released when dropped.
+struct MemoryLock
+{
+    /// The locks of the backend.
+    locks: Locks,
+    /// The path of the lock file.
+    path: PathBuf,
+    /// How the lock is held.
+    mode: LockMode,
+}
+
+impl Drop for MemoryLock
+{
+    fn drop(&mut self)
+    {
+        let mut locks = lock::recover(self.locks.lock());
+
+        if let Some(holders) = locks.get_mut(&self.path)
+        {
+            match self.mode
+            {
+                LockMode::Shared => holders.shared -= 1,
+                LockMode::Exclusive => holders.exclusive = false,
+            }
+
+            if holders.shared == 0 && !holders.exclusive
+            {
+                locks.remove(&self.path);
+            }
+        }
+    }
+}
+
+/// A backend keeping all files in memory.
+///
+/// Clones share the same files and locks, so several stores can be opened on
+/// one in-memory tree. Nothing is persisted once the last clone is dropped.
+/// Locks are kept apart from the files, so taking one creates no lock file.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend
+{
+    /// The files and directories, keyed by path.
+    nodes: Arc<Mutex<BTreeMap<PathBuf, Node>>>,
+    /// The held locks.
+    locks: Locks,
+}
+
+impl MemoryBackend
+{
+    /// Create a new, empty in-memory backend.
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Lock the file tree.
+    fn nodes(&self) -> MutexGuard<'_, BTreeMap<PathBuf, Node>>
+    {
+        lock::recover(self.nodes.lock())
+    }
+
+    /// Check that the parent directory of `path` exists.
+    fn check_parent(nodes: &BTreeMap<PathBuf, Node>, path: &Path, op: Operation) -> Result<()>
+    {
+        match path.parent()
+        {
+            Some(parent) if !parent.as_os_str().is_empty() => match nodes.get(parent)
+            {
+                Some(Node::Dir) => Ok(()),
+                _ => Err(error(parent, op, ErrorKind::NotFound)),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Write a file, optionally failing if it already exists.
+    fn write(&self, path: &Path, bytes: &[u8], create_new: bool) -> Result<()>
+    {
+        let mut nodes = self.nodes();
+
+        Self::check_parent(&nodes, path, Operation::Write)?;
+
+        match nodes.get(path)
+        {
+            Some(Node::Dir) => return Err(error(path, Operation::Write, ErrorKind::IsADirectory)),
+            Some(Node::File { .. }) if create_new =>
+            {
+                return Err(error(path, Operation::Write, ErrorKind::AlreadyExists))
+            }
+            _ =>
+            {}
+        }
+
+        nodes.insert(
+            path.to_path_buf(),
+            Node::File {
+                bytes: bytes.to_vec(),
+                modified: SystemTime::now(),
+            },
+        );
+
+        Ok(())
+    }
+}
+
+impl Backend for MemoryBackend
+{
+    fn read(&self, path: &Path) -> Result<Vec<u8>>
+    {
+        match self.nodes().get(path)
+        {
+            Some(Node::File { bytes, .. }) => Ok(bytes.clone()),
+            Some(Node::Dir) => Err(error(path, Operation::Read, ErrorKind::IsADirectory)),
+            None => Err(error(path, Operation::Read, ErrorKind::NotFound)),
+        }
+    }
+
+    fn write_atomic(&self, path: &Path, bytes: &[u8], _durability: Durability) -> Result<()>
+    {
+        self.write(path, bytes, false)
+    }
+
+    fn write_new(&self, path: &Path, bytes: &[u8], _durability: Durability) -> Result<()>
+    {
+        self.write(path, bytes, true)
+    }
+
+    fn remove(&self, path: &Path) -> Result<()>
+    {
+        let mut nodes = self.nodes();
+
+        match nodes.get(path)
+        {
+            Some(Node::File { .. }) =>
+            {
+                nodes.remove(path);
+                Ok(())
+            }
+            Some(Node::Dir) => Err(error(path, Operation::Remove, ErrorKind::IsADirectory)),
+            None => Err(error(path, Operation::Remove, ErrorKind::NotFound)),
+        }
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>>
+    {
+        let nodes = self.nodes();
+
+        match nodes.get(dir)
+        {
+            Some(Node::Dir) =>
+            {}
+            Some(Node::File { .. }) =>
+            {
+                return Err(error(dir, Operation::Directory, ErrorKind::NotADirectory))
+            }
+            None => return Err(error(dir, Operation::Directory, ErrorKind::NotFound)),
+        }
+
+        Ok(nodes
+            .range(dir.to_path_buf()..)
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(dir))
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+
+    fn exists(&self, path: &Path) -> bool
+    {
+        self.nodes().contains_key(path)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata>
+    {
+        match self.nodes().get(path)
+        {
+            Some(Node::File { bytes, modified }) => Ok(Metadata {
+                is_dir: false,
+                len: bytes.len() as u64,
+                modified: *modified,
+            }),
+            Some(Node::Dir) => Ok(Metadata {
+                is_dir: true,
+                len: 0,
+                modified: SystemTime::UNIX_EPOCH,
+            }),
+            None => Err(error(path, Operation::Read, ErrorKind::NotFound)),
+        }
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()>
+    {
+        let mut nodes = self.nodes();
+
+        for ancestor in path.ancestors().filter(|path| !path.as_os_str().is_empty())
+        {
+            match nodes.get(ancestor)
+            {
+                Some(Node::Dir) => break,
+                Some(Node::File { .. }) =>
+                {
+                    return Err(error(
+                        ancestor,
+                        Operation::Directory,
+                        ErrorKind::NotADirectory,
+                    ))
+                }
+                None =>
+                {
+                    nodes.insert(ancestor.to_path_buf(), Node::Dir);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()>
+    {
+        let mut nodes = self.nodes();
+
+        match nodes.get(path)
+        {
+            Some(Node::Dir) => nodes.retain(|node, _| !node.starts_with(path)),
+            Some(Node::File { .. }) =>
+            {
+                return Err(error(path, Operation::Remove, ErrorKind::NotADirectory))
+            }
+            None => return Err(error(path, Operation::Remove, ErrorKind::NotFound)),
+        }
+
+        Ok(())
+    }
+
+    fn try_lock(&self, path: &Path, mode: LockMode) -> Result<Option<Lock>>
+    {
+        let mut locks = lock::recover(self.locks.lock());
+        let holders = locks.entry(path.to_path_buf()).or_default();
+
+        match mode
+        {
+            _ if holders.exclusive => return Ok(None),
+            LockMode::Shared => holders.shared += 1,
+            LockMode::Exclusive if holders.shared > 0 => return Ok(None),
+            LockMode::Exclusive => holders.exclusive = true,
+        }
+
+        let handle = MemoryLock {
+            locks: self.locks.clone(),
+            path: path.to_path_buf(),
+            mode,
+        };
+
+        Ok(Some(Lock::new(path.to_path_buf(), mode, handle)))
+    }
+}
+
+/// Create an error as the filesystem would have reported it.
+fn error(path: &Path, op: Operation, kind: ErrorKind) -> StoreError
+{
+    StoreError::io(path, op, kind.into())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Test that the in-memory backend behaves like a directory tree.
+    #[test]
+    fn test_memory_backend() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let dir = Path::new("data/nested");
+        let path = dir.join("value.json");
+
+        assert!(backend.write_atomic(&path, b"1", Durability::Full).is_err());
+
+        backend.create_dir_all(dir)?;
+        backend.write_atomic(&path, b"1", Durability::Full)?;
+        backend.write_atomic(&path, b"2", Durability::Full)?;
+
+        assert!(matches!(
+            backend.write_new(&path, b"3", Durability::Full),
+            Err(StoreError::AlreadyExists { .. })
+        ));
+        assert_eq!(backend.read(&path)?, b"2");
+        assert_eq!(backend.metadata(&path)?.len, 1);
+        assert_eq!(backend.list(Path::new("data"))?, vec![dir.to_path_buf()]);
+        assert_eq!(backend.list(dir)?, vec![path.clone()]);
+
+        backend.remove_dir_all(Path::new("data"))?;
+
+        assert!(!backend.exists(&path));
+        assert!(!backend.exists(dir));
+
+        Ok(())
+    }
+}
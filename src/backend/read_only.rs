@@ -0,0 +1,99 @@
+use super::{Backend, Metadata};
+use crate::{
+    error::{Result, StoreError},
+    Durability, Lock, LockMode,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A backend refusing every write to the backend it wraps.
+///
+/// Used for stores opened with [`Options::read_only`](crate::Options::read_only).
+/// Shared locks are still taken, since they only keep writers out.
+#[derive(Debug)]
+pub(crate) struct ReadOnlyBackend
+{
+    /// The wrapped backend.
+    inner: Arc<dyn Backend>,
+}
+
+impl ReadOnlyBackend
+{
+    /// Wrap a backend.
+    pub(crate) fn new(inner: Arc<dyn Backend>) -> Self
+    {
+        Self { inner }
+    }
+}
+
+impl Backend for ReadOnlyBackend
+{
+    fn read(&self, path: &Path) -> Result<Vec<u8>>
+    {
+        self.inner.read(path)
+    }
+
+    fn write_atomic(&self, path: &Path, _bytes: &[u8], _durability: Durability) -> Result<()>
+    {
+        Err(refused(path))
+    }
+
+    fn write_new(&self, path: &Path, _bytes: &[u8], _durability: Durability) -> Result<()>
+    {
+        Err(refused(path))
+    }
+
+    fn remove(&self, path: &Path) -> Result<()>
+    {
+        Err(refused(path))
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>>
+    {
+        self.inner.list(dir)
+    }
+
+    fn exists(&self, path: &Path) -> bool
+    {
+        self.inner.exists(path)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata>
+    {
+        self.inner.metadata(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()>
+    {
+        match self.inner.metadata(path)
+        {
+            Ok(metadata) if metadata.is_dir => Ok(()),
+            Ok(_) => Err(refused(path)),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()>
+    {
+        Err(refused(path))
+    }
+
+    fn try_lock(&self, path: &Path, mode: LockMode) -> Result<Option<Lock>>
+    {
+        match mode
+        {
+            LockMode::Shared => self.inner.try_lock(path, mode),
+            LockMode::Exclusive => Err(refused(path)),
+        }
+    }
+}
+
+/// Create the error for a refused write.
+fn refused(path: &Path) -> StoreError
+{
+    StoreError::ReadOnly {
+        path: path.to_path_buf(),
+    }
+}
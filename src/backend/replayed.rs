@@ -0,0 +1,158 @@
+use super::{Backend, Metadata};
+use crate::{
+    error::{Result, StoreError},
+    Durability, Lock, LockMode,
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+/// A backend showing changes to the backend it wraps as if they were
+/// written, without writing them.
+///
+/// Used for read-only stores with an intent log they cannot replay, see
+/// [`Batch`](crate::Batch). Writes go to the wrapped backend.
+#[derive(Debug)]
+pub(crate) struct ReplayedBackend
+{
+    /// The wrapped backend.
+    inner: Arc<dyn Backend>,
+    /// The new contents of every changed file, or `None` if it was removed.
+    files: BTreeMap<PathBuf, Option<Vec<u8>>>,
+}
+
+impl ReplayedBackend
+{
+    /// Wrap a backend, showing the given files in place of its own.
+    pub(crate) fn new(inner: Arc<dyn Backend>, files: BTreeMap<PathBuf, Option<Vec<u8>>>) -> Self
+    {
+        Self { inner, files }
+    }
+
+    /// Check whether a changed file that was not removed is inside `dir`.
+    fn contains(&self, dir: &Path) -> bool
+    {
+        self.files
+            .iter()
+            .any(|(path, bytes)| bytes.is_some() && path != dir && path.starts_with(dir))
+    }
+}
+
+impl Backend for ReplayedBackend
+{
+    fn read(&self, path: &Path) -> Result<Vec<u8>>
+    {
+        match self.files.get(path)
+        {
+            Some(Some(bytes)) => Ok(bytes.clone()),
+            Some(None) => Err(not_found(path)),
+            None => self.inner.read(path),
+        }
+    }
+
+    fn write_atomic(&self, path: &Path, bytes: &[u8], durability: Durability) -> Result<()>
+    {
+        self.inner.write_atomic(path, bytes, durability)
+    }
+
+    fn write_new(&self, path: &Path, bytes: &[u8], durability: Durability) -> Result<()>
+    {
+        self.inner.write_new(path, bytes, durability)
+    }
+
+    fn remove(&self, path: &Path) -> Result<()>
+    {
+        self.inner.remove(path)
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>>
+    {
+        let mut paths = match self.inner.list(dir)
+        {
+            Ok(paths) => paths,
+            Err(StoreError::NotFound { .. }) if self.contains(dir) => Vec::new(),
+            Err(error) => return Err(error),
+        };
+
+        paths.retain(|path| !matches!(self.files.get(path), Some(None)));
+
+        for (path, bytes) in &self.files
+        {
+            let child = match (bytes, path.strip_prefix(dir))
+            {
+                (Some(_), Ok(relative)) => relative.components().next(),
+                _ => None,
+            };
+
+            if let Some(child) = child
+            {
+                paths.push(dir.join(child));
+            }
+        }
+
+        paths.sort();
+        paths.dedup();
+
+        Ok(paths)
+    }
+
+    fn exists(&self, path: &Path) -> bool
+    {
+        match self.files.get(path)
+        {
+            Some(bytes) => bytes.is_some(),
+            None => self.inner.exists(path) || self.contains(path),
+        }
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata>
+    {
+        match self.files.get(path)
+        {
+            Some(Some(bytes)) => Ok(Metadata {
+                is_dir: false,
+                len: bytes.len() as u64,
+                modified: self
+                    .inner
+                    .metadata(path)
+                    .map_or(SystemTime::UNIX_EPOCH, |metadata| metadata.modified),
+            }),
+            Some(None) => Err(not_found(path)),
+            None => match self.inner.metadata(path)
+            {
+                Err(StoreError::NotFound { .. }) if self.contains(path) => Ok(Metadata {
+                    is_dir: true,
+                    len: 0,
+                    modified: SystemTime::UNIX_EPOCH,
+                }),
+                result => result,
+            },
+        }
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()>
+    {
+        self.inner.create_dir_all(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()>
+    {
+        self.inner.remove_dir_all(path)
+    }
+
+    fn try_lock(&self, path: &Path, mode: LockMode) -> Result<Option<Lock>>
+    {
+        self.inner.try_lock(path, mode)
+    }
+}
+
+/// Create the error for a file that was removed.
+fn not_found(path: &Path) -> StoreError
+{
+    StoreError::NotFound {
+        path: path.to_path_buf(),
+    }
+}
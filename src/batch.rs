@@ -0,0 +1,827 @@
+//! Batched writes committed as a single unit through an intent log.
+//!
+//! Committing a batch first writes every change to a hidden log file in the
+//! store's directory. Writing the log is the commit point: once it is on disk
+//! the changes are applied to the entries and the log is removed. If the
+//! process crashes in between, opening the store replays the log, and if it
+//! crashes before the log is complete none of the changes were applied yet.
+//!
+//! A read-only store cannot replay the log, so it shows the changes as if
+//! they were applied while leaving the files untouched, until a writable store
+//! is opened.
+
+use super::{
+    backend::ReplayedBackend,
+    error::{Result, StoreError},
+    key::{self, StoreKey},
+    lock, migration, Fingerprint, Format, FormatError, Json, Options, Store, Stored,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    mem,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// The name of the intent log inside a store's directory.
+pub(crate) const FILE_NAME: &str = ".batch.json";
+
+/// The characters of base64, in order of their value.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A set of saves and deletes applied to a store as a single unit.
+///
+/// Returned by [`Store::batch`]. Nothing is written until the batch is
+/// committed, and dropping it discards the changes. A later change to the
+/// same key replaces an earlier one.
+///
+/// # Example
+///
+/// ```no_run
+/// use store::Store;
+///
+/// # fn main() -> store::Result<()> {
+/// let mut store: Store<String, u64> = Store::new("accounts")?;
+///
+/// store
+///     .batch()
+///     .save("alice", 50)
+///     .save("bob", 150)
+///     .delete("carol")
+///     .commit()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Batch<'a, K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The store the batch is applied to.
+    store: &'a mut Store<K, T, F>,
+    /// The new value of every changed key, or `None` to delete it.
+    changes: BTreeMap<K, Option<T>>,
+}
+
+/// The contents of an intent log.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Log
+{
+    /// The changes to apply, in order.
+    changes: Vec<Change>,
+}
+
+/// A single change recorded in an intent log.
+#[derive(Debug, Serialize, Deserialize)]
+enum Change
+{
+    /// Write a file.
+    Save
+    {
+        /// The path of the file relative to the store, split on `/`.
+        path: String,
+        /// The contents of the file.
+        contents: Contents,
+    },
+    /// Delete a file if it exists.
+    Delete
+    {
+        /// The path of the file relative to the store, split on `/`.
+        path: String,
+    },
+}
+
+/// The contents of a file recorded in an intent log.
+#[derive(Debug, Serialize, Deserialize)]
+enum Contents
+{
+    /// Contents that are valid UTF-8, as written by every textual format.
+    Text(String),
+    /// Any other contents, encoded as base64.
+    Base64(String),
+}
+
+impl Contents
+{
+    /// Record the contents of a file, as text if they are valid UTF-8.
+    fn new(bytes: &[u8]) -> Self
+    {
+        match std::str::from_utf8(bytes)
+        {
+            Ok(text) => Self::Text(text.to_string()),
+            Err(_) => Self::Base64(to_base64(bytes)),
+        }
+    }
+
+    /// Get the recorded contents back from the intent log at `log_path`.
+    fn decode(&self, log_path: &Path) -> Result<Vec<u8>>
+    {
+        match self
+        {
+            Self::Text(text) => Ok(text.clone().into_bytes()),
+            Self::Base64(text) => from_base64(text).ok_or_else(|| {
+                StoreError::corrupt(log_path, FormatError::new("invalid base64 in intent log"))
+            }),
+        }
+    }
+}
+
+impl<'a, K, T, F> Batch<'a, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Create a batch of changes for a store.
+    pub(super) fn new(store: &'a mut Store<K, T, F>, changes: BTreeMap<K, Option<T>>) -> Self
+    {
+        Self { store, changes }
+    }
+
+    /// Save a value when the batch is committed.
+    pub fn save<Q>(&mut self, key: Q, value: T) -> &mut Self
+    where
+        Q: Into<K>,
+    {
+        self.changes.insert(key.into(), Some(value));
+        self
+    }
+
+    /// Delete an entry when the batch is committed. Deleting an entry that
+    /// does not exist does nothing.
+    pub fn delete<Q>(&mut self, key: Q) -> &mut Self
+    where
+        Q: Into<K>,
+    {
+        self.changes.insert(key.into(), None);
+        self
+    }
+
+    /// Get the number of keys changed by the batch.
+    pub fn len(&self) -> usize
+    {
+        self.changes.len()
+    }
+
+    /// Check whether the batch changes nothing.
+    pub fn is_empty(&self) -> bool
+    {
+        self.changes.is_empty()
+    }
+
+    /// Apply every change to the store as a single unit.
+    ///
+    /// Fails without changing anything if a key cannot be encoded or a value
+    /// cannot be serialized. If applying the changes fails after the log was
+    /// written, the store keeps the changes applied so far and the rest are
+    /// applied by the next commit or when the store is next opened. The
+    /// store's lock is held while the changes are written.
+    pub fn commit(&mut self) -> Result<()>
+    {
+        if self.changes.is_empty()
+        {
+            return Ok(());
+        }
+
+        let store = &mut *self.store;
+        let mut log = Log::default();
+        let mut written = Vec::with_capacity(self.changes.len());
+
+        for (key, value) in &self.changes
+        {
+            let path = store.path_for(key)?;
+            let relative = relative(&store.path, &path);
+            let mut contents = None;
+
+            log.changes.push(match value
+            {
+                Some(value) =>
+                {
+                    let bytes = migration::encode(
+                        &store.options.format,
+                        store.options.schema_version,
+                        value,
+                    )
+                    .map_err(|error| StoreError::serialize(&path, error))?;
+
+                    let change = Change::Save {
+                        path: relative,
+                        contents: Contents::new(&bytes),
+                    };
+
+                    contents = Some(bytes);
+                    change
+                }
+                None => Change::Delete { path: relative },
+            });
+
+            written.push((path, contents));
+        }
+
+        let _held = lock::write(store.options.store_lock.as_ref())?;
+
+        // Writing the log replaces any earlier one, so the changes of an
+        // earlier batch that were not all applied are applied first.
+        if let Some(earlier) = read_log(&store.path, &store.options)?
+        {
+            let result = apply(&store.path, &earlier, &store.options);
+
+            reload(store, &earlier)?;
+            result.map_err(|(_, error)| error)?;
+        }
+
+        write_log(&store.path, &log, &store.options)?;
+
+        let result = apply(&store.path, &log, &store.options);
+        let applied = match &result
+        {
+            Ok(()) => log.changes.len(),
+            Err((applied, _)) => *applied,
+        };
+
+        for ((key, value), (path, contents)) in mem::take(&mut self.changes)
+            .into_iter()
+            .zip(written)
+            .take(applied)
+        {
+            let fingerprint =
+                contents.map(|bytes| Fingerprint::of(&*store.options.backend, &path, &bytes));
+
+            store.touch(&key);
+
+            match value
+            {
+                Some(value) => match store.data.get_mut(&key)
+                {
+                    Some(stored) =>
+                    {
+                        stored.value = value;
+                        stored.fingerprint = fingerprint;
+                        stored.modified = false;
+                    }
+                    None =>
+                    {
+                        let mut stored = Stored::with_value(path, value, &store.options);
+
+                        store.sequence += 1;
+                        stored.sequence = store.sequence;
+                        stored.fingerprint = fingerprint;
+                        store.data.insert(key, stored);
+                    }
+                },
+                None =>
+                {
+                    store.data.remove(&key);
+                    store.prune(&path)?;
+                }
+            }
+        }
+
+        result.map_err(|(_, error)| error)
+    }
+}
+
+/// Replay the intent log of the store at `dir`, if there is one.
+///
+/// A read-only store only replays the log in memory, by replacing the
+/// backend in `options` with one showing the changes. Returns whether a log
+/// was replayed.
+pub(crate) fn recover<F>(dir: &Path, options: &mut Options<F>) -> Result<bool>
+where
+    F: Format,
+{
+    if !options.backend.exists(&dir.join(FILE_NAME))
+    {
+        return Ok(false);
+    }
+
+    if options.read_only
+    {
+        let Some(log) = read_log(dir, options)?
+        else
+        {
+            return Ok(false);
+        };
+
+        let log_path = dir.join(FILE_NAME);
+        let mut files = BTreeMap::from([(log_path.clone(), None)]);
+
+        for change in &log.changes
+        {
+            let (path, bytes) = match change
+            {
+                Change::Save { path, contents } => (path, Some(contents.decode(&log_path)?)),
+                Change::Delete { path } => (path, None),
+            };
+
+            files.insert(absolute(dir, path, &log_path)?, bytes);
+        }
+
+        options.backend = Arc::new(ReplayedBackend::new(options.backend.clone(), files));
+
+        return Ok(true);
+    }
+
+    let _held = lock::write(options.store_lock.as_ref())?;
+
+    // Someone else may have replayed the log while waiting for the lock.
+    let Some(log) = read_log(dir, options)?
+    else
+    {
+        return Ok(false);
+    };
+
+    apply(dir, &log, options).map_err(|(_, error)| error)?;
+
+    Ok(true)
+}
+
+/// Read the intent log of the store at `dir`, returning `None` if there is
+/// none.
+fn read_log<F>(dir: &Path, options: &Options<F>) -> Result<Option<Log>>
+where
+    F: Format,
+{
+    let path = dir.join(FILE_NAME);
+
+    let bytes = match options.backend.read(&path)
+    {
+        Ok(bytes) => bytes,
+        Err(StoreError::NotFound { .. }) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+
+    Json.deserialize(&bytes)
+        .map(Some)
+        .map_err(|error| StoreError::corrupt(&path, error))
+}
+
+/// Write the intent log of the store at `dir`, committing its changes.
+fn write_log<F>(dir: &Path, log: &Log, options: &Options<F>) -> Result<()>
+where
+    F: Format,
+{
+    let path = dir.join(FILE_NAME);
+    let bytes = Json
+        .serialize(log)
+        .map_err(|error| StoreError::serialize(&path, error))?;
+
+    options
+        .backend
+        .write_atomic(&path, &bytes, options.durability)
+}
+
+/// Apply the changes of a committed intent log and remove it.
+///
+/// Every change can be applied any number of times, so a log that was only
+/// partially applied can be replayed from the start. Fails with the number of
+/// changes that were applied before the error.
+fn apply<F>(dir: &Path, log: &Log, options: &Options<F>) -> Result<(), (usize, StoreError)>
+where
+    F: Format,
+{
+    let log_path = dir.join(FILE_NAME);
+
+    for (index, change) in log.changes.iter().enumerate()
+    {
+        apply_change(dir, change, &log_path, options).map_err(|error| (index, error))?;
+    }
+
+    options
+        .backend
+        .remove(&log_path)
+        .map_err(|error| (log.changes.len(), error))
+}
+
+/// Apply a single change of the intent log at `log_path`.
+fn apply_change<F>(dir: &Path, change: &Change, log_path: &Path, options: &Options<F>) -> Result<()>
+where
+    F: Format,
+{
+    match change
+    {
+        Change::Save { path, contents } =>
+        {
+            let path = absolute(dir, path, log_path)?;
+            let bytes = contents.decode(log_path)?;
+
+            if let Some(parent) = path.parent()
+            {
+                options.backend.create_dir_all(parent)?;
+            }
+
+            options
+                .backend
+                .write_atomic(&path, &bytes, options.durability)
+        }
+        Change::Delete { path } => match options.backend.remove(&absolute(dir, path, log_path)?)
+        {
+            Ok(()) | Err(StoreError::NotFound { .. }) => Ok(()),
+            Err(error) => Err(error),
+        },
+    }
+}
+
+/// Bring the entries changed by an intent log in line with their files.
+fn reload<K, T, F>(store: &mut Store<K, T, F>, log: &Log) -> Result<()>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    let log_path = store.path.join(FILE_NAME);
+
+    for change in &log.changes
+    {
+        let (Change::Save { path, .. } | Change::Delete { path }) = change;
+        let path = absolute(&store.path, path, &log_path)?;
+
+        let Some(key) = store.key_for(&path)
+        else
+        {
+            continue;
+        };
+
+        store.touch(&key);
+
+        match (
+            Stored::open_with(&path, &store.options)?,
+            store.data.get_mut(&key),
+        )
+        {
+            (Some(loaded), Some(stored)) =>
+            {
+                stored.value = loaded.value;
+                stored.fingerprint = loaded.fingerprint;
+                stored.modified = false;
+            }
+            (Some(mut loaded), None) =>
+            {
+                store.sequence += 1;
+                loaded.sequence = store.sequence;
+                store.data.insert(key, loaded);
+            }
+            (None, _) =>
+            {
+                store.data.remove(&key);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the path of a file relative to the store's directory, split on `/`.
+fn relative(dir: &Path, path: &Path) -> String
+{
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Get the path of a file recorded in the intent log, refusing any path that
+/// would leave the store's directory.
+fn absolute(dir: &Path, relative: &str, log_path: &Path) -> Result<PathBuf>
+{
+    let mut path = dir.to_path_buf();
+
+    for part in relative.split('/')
+    {
+        key::check(part).map_err(|error| StoreError::corrupt(log_path, FormatError::new(error)))?;
+        path.push(part);
+    }
+
+    Ok(path)
+}
+
+/// Encode bytes as padded base64.
+fn to_base64(bytes: &[u8]) -> String
+{
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3)
+    {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| {
+            bits | u32::from(*byte) << (16 - 8 * index)
+        });
+
+        for index in 0..4
+        {
+            if index <= chunk.len()
+            {
+                text.push(char::from(BASE64[(bits >> (18 - 6 * index) & 63) as usize]));
+            }
+            else
+            {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+/// Decode padded base64 into bytes.
+fn from_base64(text: &str) -> Option<Vec<u8>>
+{
+    if !text.len().is_multiple_of(4)
+    {
+        return None;
+    }
+
+    let chunks = text.len() / 4;
+    let mut bytes = Vec::with_capacity(chunks * 3);
+
+    for (number, chunk) in text.as_bytes().chunks(4).enumerate()
+    {
+        let padding = chunk.iter().rev().take_while(|char| **char == b'=').count();
+
+        if padding > 2 || (padding > 0 && number + 1 < chunks)
+        {
+            return None;
+        }
+
+        let mut bits = 0u32;
+
+        for (index, char) in chunk[..4 - padding].iter().enumerate()
+        {
+            let value = BASE64.iter().position(|known| known == char)?;
+
+            bits |= (value as u32) << (18 - 6 * index);
+        }
+
+        bytes.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{error::Operation, Backend, Durability, Lock, LockMode, MemoryBackend, Metadata};
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    /// A backend failing writes to files named `fail.json` while `failing`
+    /// is set.
+    #[derive(Debug, Clone)]
+    struct Failing
+    {
+        /// The backend writes go to otherwise.
+        inner: MemoryBackend,
+        /// Whether writes fail.
+        failing: Arc<AtomicBool>,
+    }
+
+    impl Failing
+    {
+        /// Fail if the path is the failing file.
+        fn check(&self, path: &Path) -> Result<()>
+        {
+            if self.failing.load(Ordering::SeqCst) && path.ends_with("fail.json")
+            {
+                let error = io::Error::other("injected failure");
+
+                return Err(StoreError::io(path, Operation::Write, error));
+            }
+
+            Ok(())
+        }
+    }
+
+    impl Backend for Failing
+    {
+        fn read(&self, path: &Path) -> Result<Vec<u8>>
+        {
+            self.inner.read(path)
+        }
+
+        fn write_atomic(&self, path: &Path, bytes: &[u8], durability: Durability) -> Result<()>
+        {
+            self.check(path)?;
+            self.inner.write_atomic(path, bytes, durability)
+        }
+
+        fn write_new(&self, path: &Path, bytes: &[u8], durability: Durability) -> Result<()>
+        {
+            self.check(path)?;
+            self.inner.write_new(path, bytes, durability)
+        }
+
+        fn remove(&self, path: &Path) -> Result<()>
+        {
+            self.inner.remove(path)
+        }
+
+        fn list(&self, dir: &Path) -> Result<Vec<PathBuf>>
+        {
+            self.inner.list(dir)
+        }
+
+        fn exists(&self, path: &Path) -> bool
+        {
+            self.inner.exists(path)
+        }
+
+        fn metadata(&self, path: &Path) -> Result<Metadata>
+        {
+            self.inner.metadata(path)
+        }
+
+        fn create_dir_all(&self, path: &Path) -> Result<()>
+        {
+            self.inner.create_dir_all(path)
+        }
+
+        fn remove_dir_all(&self, path: &Path) -> Result<()>
+        {
+            self.inner.remove_dir_all(path)
+        }
+
+        fn try_lock(&self, path: &Path, mode: LockMode) -> Result<Option<Lock>>
+        {
+            self.inner.try_lock(path, mode)
+        }
+    }
+
+    /// Test that a batch is applied as a whole and replayed after a crash.
+    #[test]
+    fn test_batch() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, u32> = Store::with_options("test", options.clone())?;
+
+        store.save("a", 1)?;
+        store
+            .batch()
+            .save("b", 2)
+            .save("c", 3)
+            .delete("a")
+            .commit()?;
+
+        assert_eq!(store.keys().collect::<Vec<_>>(), vec!["b", "c"]);
+        assert!(!backend.exists(Path::new("test/a.json")));
+        assert!(!backend.exists(Path::new("test/.batch.json")));
+
+        let log = Log {
+            changes: vec![
+                Change::Save {
+                    path: String::from("d.json"),
+                    contents: Contents::new(b"4"),
+                },
+                Change::Delete {
+                    path: String::from("b.json"),
+                },
+            ],
+        };
+
+        write_log(Path::new("test"), &log, &options)?;
+
+        let (store, report) = Store::<String, u32>::open_with("test", options.clone())?;
+
+        assert!(report.is_clean());
+        assert!(report.skipped.is_empty());
+        assert_eq!(store.keys().collect::<Vec<_>>(), vec!["c", "d"]);
+        assert!(!backend.exists(Path::new("test/.batch.json")));
+
+        let log = Log {
+            changes: vec![Change::Delete {
+                path: String::from("../escape.json"),
+            }],
+        };
+
+        write_log(Path::new("test"), &log, &options)?;
+
+        assert!(matches!(
+            Store::<String, u32>::with_options("test", options),
+            Err(StoreError::Corrupt { .. })
+        ));
+
+        Ok(())
+    }
+
+    /// Test that a batch that failed halfway is finished before the next one
+    /// and the store keeps what was applied.
+    #[test]
+    fn test_failed_apply() -> Result<()>
+    {
+        let backend = Failing {
+            inner: MemoryBackend::new(),
+            failing: Arc::new(AtomicBool::new(true)),
+        };
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, u32> = Store::with_options("test", options.clone())?;
+
+        assert!(store.batch().save("a", 1).save("fail", 2).commit().is_err());
+        assert_eq!(store.keys().collect::<Vec<_>>(), vec!["a"]);
+        assert!(backend.exists(Path::new("test/.batch.json")));
+
+        assert!(store.batch().save("z", 3).commit().is_err());
+        assert_eq!(store.keys().collect::<Vec<_>>(), vec!["a"]);
+        assert!(!backend.exists(Path::new("test/z.json")));
+
+        backend.failing.store(false, Ordering::SeqCst);
+        store.batch().save("z", 3).commit()?;
+
+        assert_eq!(store.keys().collect::<Vec<_>>(), vec!["a", "fail", "z"]);
+        assert_eq!(store.get("fail"), Some(&2));
+        assert!(!backend.exists(Path::new("test/.batch.json")));
+
+        let (reopened, report) = Store::<String, u32>::open_with("test", options)?;
+
+        assert!(report.is_clean());
+        assert_eq!(
+            reopened.iter().collect::<Vec<_>>(),
+            store.iter().collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    /// Test that a read-only store shows the changes of an intent log it
+    /// cannot replay without writing them.
+    #[test]
+    fn test_read_only_recover() -> Result<()>
+    {
+        let backend = Failing {
+            inner: MemoryBackend::new(),
+            failing: Arc::new(AtomicBool::new(true)),
+        };
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, u32> = Store::with_options("test", options.clone())?;
+
+        store.save("z", 9)?;
+
+        assert!(store
+            .batch()
+            .save("a", 1)
+            .save("fail", 2)
+            .delete("z")
+            .commit()
+            .is_err());
+
+        let (reader, report) = Store::<String, u32>::open_with("test", options.read_only())?;
+
+        assert!(report.is_clean());
+        assert_eq!(
+            reader.iter().collect::<Vec<_>>(),
+            vec![(&String::from("a"), &1), (&String::from("fail"), &2)]
+        );
+        assert!(backend.exists(Path::new("test/.batch.json")));
+        assert!(backend.exists(Path::new("test/z.json")));
+        assert!(!backend.exists(Path::new("test/fail.json")));
+
+        Ok(())
+    }
+
+    /// Test that textual contents are logged as text and anything else as
+    /// base64.
+    #[test]
+    fn test_contents() -> Result<()>
+    {
+        let log_path = Path::new("test/.batch.json");
+
+        assert!(
+            matches!(Contents::new(b"{\"a\": 1}"), Contents::Text(text) if text == "{\"a\": 1}")
+        );
+
+        for bytes in [
+            &b""[..],
+            b"\xff",
+            b"\xff\x00",
+            b"\xff\x00\x80",
+            b"\xfe\xff\x00\x01",
+        ]
+        {
+            let contents = Contents::new(bytes);
+
+            assert!(bytes.is_empty() || matches!(contents, Contents::Base64(_)));
+            assert_eq!(contents.decode(log_path)?, bytes);
+        }
+
+        assert_eq!(to_base64(b"\xfb\xff"), "+/8=");
+
+        for invalid in ["abc", "a===", "ab==abcd", "ab!d"]
+        {
+            assert!(matches!(
+                Contents::Base64(String::from(invalid)).decode(log_path),
+                Err(StoreError::Corrupt { .. })
+            ));
+        }
+
+        Ok(())
+    }
+}
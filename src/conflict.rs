@@ -0,0 +1,61 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+/// A function merging the value on disk into the value being saved.
+type Merge<T> = Arc<dyn Fn(&mut T, T) + Send + Sync>;
+
+/// What a stored value does when its file was changed by someone else since
+/// it was last read or written, see [`Stored::is_stale`](super::Stored::is_stale).
+#[derive(Default)]
+pub enum ConflictPolicy<T>
+{
+    /// Overwrite the file, discarding the other change.
+    #[default]
+    Overwrite,
+    /// Fail with [`StoreError::Conflict`](super::StoreError::Conflict)
+    /// without writing anything.
+    Fail,
+    /// Merge the value on disk into the value being saved, then write the
+    /// result. A file that was deleted is written without merging.
+    Merge(Merge<T>),
+}
+
+impl<T> ConflictPolicy<T>
+{
+    /// Create a policy merging the value on disk, passed as the second
+    /// argument, into the value being saved.
+    pub fn merge<M>(merge: M) -> Self
+    where
+        M: Fn(&mut T, T) + Send + Sync + 'static,
+    {
+        Self::Merge(Arc::new(merge))
+    }
+}
+
+impl<T> Clone for ConflictPolicy<T>
+{
+    fn clone(&self) -> Self
+    {
+        match self
+        {
+            Self::Overwrite => Self::Overwrite,
+            Self::Fail => Self::Fail,
+            Self::Merge(merge) => Self::Merge(merge.clone()),
+        }
+    }
+}
+
+impl<T> Debug for ConflictPolicy<T>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Self::Overwrite => f.write_str("Overwrite"),
+            Self::Fail => f.write_str("Fail"),
+            Self::Merge(_) => f.write_str("Merge(..)"),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use super::{
+    error::{Result, StoreError},
+    key::{self, StoreKey},
+    lock::{self, LockMode},
+    manifest::{self, Manifest},
+    scan::ScanReport,
+    Format, Json, Options, Store,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A directory holding named collections, each a store of its own type.
+///
+/// Every collection is a store in a directory of its own, which records its
+/// format and tag in its manifest when it is first opened. Opening it again
+/// with anything else fails with [`StoreError::Mismatch`], see [`Manifest`]. The collections of a database are the stores inside its
+/// directory, so there is nothing else to keep in sync between handles.
+///
+/// # Example
+///
+/// ```no_run
+/// use store::Database;
+///
+/// # fn main() -> store::Result<()> {
+/// let mut db = Database::open("data")?;
+/// let (mut users, _) = db.collection::<String>("users")?;
+/// let (mut visits, _) = db.keyed_collection::<u32, u64>("visits")?;
+///
+/// users.save("milan", String::from("Milan"))?;
+/// visits.save(1u32, 10)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Database<F = Json>
+{
+    /// The path to the database.
+    path: PathBuf,
+    /// The options every collection is opened with.
+    options: Options<F>,
+}
+
+impl Database
+{
+    /// Open a database, creating its directory if needed.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_with(path, Options::new())
+    }
+}
+
+impl<F> Database<F>
+where
+    F: Format,
+{
+    /// Open a database using the given options.
+    ///
+    /// Every collection is opened with these options.
+    pub fn open_with<P>(path: P, options: Options<F>) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+
+        options.backend.create_dir_all(&path)?;
+
+        Ok(Self { path, options })
+    }
+
+    /// Open a collection with string keys.
+    ///
+    /// See [`Database::keyed_collection`].
+    pub fn collection<T>(&mut self, name: &str) -> Result<(Store<String, T, F>, ScanReport)>
+    where
+        for<'de> T: Serialize + Deserialize<'de>,
+    {
+        self.keyed_collection(name)
+    }
+
+    /// Open a collection, loading every entry that is already on disk.
+    ///
+    /// A new collection is created, unless the database is read-only. An
+    /// existing one fails with [`StoreError::Mismatch`] if it was created
+    /// with another format or tag.
+    pub fn keyed_collection<K, T>(&mut self, name: &str) -> Result<(Store<K, T, F>, ScanReport<K>)>
+    where
+        K: StoreKey,
+        for<'de> T: Serialize + Deserialize<'de>,
+    {
+        Store::open_with(self.path_for(name)?, self.options.clone())
+    }
+
+    /// Open a collection with a tag naming its key and value types.
+    ///
+    /// Opening it again with another tag fails with
+    /// [`StoreError::Mismatch`], see [`Options::tag`].
+    pub fn tagged_collection<K, T>(
+        &mut self,
+        name: &str,
+        tag: &str,
+    ) -> Result<(Store<K, T, F>, ScanReport<K>)>
+    where
+        K: StoreKey,
+        for<'de> T: Serialize + Deserialize<'de>,
+    {
+        Store::open_with(self.path_for(name)?, self.options.clone().tag(tag))
+    }
+
+    /// Get the names of the collections in the database, in order.
+    ///
+    /// Directories without a manifest are not collections and are ignored.
+    pub fn collections(&self) -> Result<Vec<String>>
+    {
+        let paths = match self.options.backend.list(&self.path)
+        {
+            Ok(paths) => paths,
+            Err(StoreError::NotFound { .. }) => Vec::new(),
+            Err(error) => return Err(error),
+        };
+
+        let mut collections = Vec::new();
+
+        for path in paths
+        {
+            if !self.options.backend.exists(&path.join(manifest::FILE_NAME))
+            {
+                continue;
+            }
+
+            if let Some(Ok(name)) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(key::decode)
+            {
+                collections.push(name);
+            }
+        }
+
+        collections.sort();
+
+        Ok(collections)
+    }
+
+    /// Get what a collection was created with, or `None` if there is no such
+    /// collection.
+    pub fn collection_info(&self, name: &str) -> Result<Option<Manifest>>
+    {
+        Manifest::read(
+            &self.path_for(name)?.join(manifest::FILE_NAME),
+            &self.options,
+        )
+    }
+
+    /// Delete a collection and every entry in it.
+    ///
+    /// The collection's lock is held exclusively while it is deleted, see
+    /// [`lock`].
+    pub fn drop_collection(&mut self, name: &str) -> Result<()>
+    {
+        let path = self.path_for(name)?;
+
+        if !self.options.backend.exists(&path.join(manifest::FILE_NAME))
+        {
+            return Err(StoreError::NotFound { path });
+        }
+
+        let _lock = lock::acquire(
+            &*self.options.backend,
+            &path.join(lock::FILE_NAME),
+            LockMode::Exclusive,
+            self.options.lock_timeout,
+        )?;
+
+        self.options.backend.remove_dir_all(&path)
+    }
+
+    /// Get the path of a collection's directory.
+    fn path_for(&self, name: &str) -> Result<PathBuf>
+    {
+        let name = key::encode(name)?;
+
+        key::check(&name)?;
+
+        Ok(self.path.join(name))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{MemoryBackend, PrettyJson};
+
+    /// Test that collections are recorded and refused with another type.
+    #[test]
+    fn test_database() -> Result<()>
+    {
+        let options = Options::new().backend(MemoryBackend::new());
+        let mut db = Database::open_with("db", options.clone())?;
+        let (mut users, _) = db.collection::<String>("users")?;
+
+        users.save("milan", String::from("Milan"))?;
+
+        let mut db = Database::open_with("db", options.clone())?;
+        let (users, report) = db.collection::<String>("users")?;
+
+        assert!(report.is_clean());
+        assert_eq!(users.get("milan"), Some(&String::from("Milan")));
+        assert_eq!(db.collections()?, vec!["users"]);
+        assert!(db
+            .tagged_collection::<String, String>("users", "user")?
+            .0
+            .contains_key("milan"));
+        assert!(matches!(
+            db.tagged_collection::<u32, u32>("users", "visit"),
+            Err(StoreError::Mismatch { what: "tag", .. })
+        ));
+
+        let mut db = Database::open_with("db", options.format(PrettyJson))?;
+
+        assert!(matches!(
+            db.collection::<String>("users"),
+            Err(StoreError::Mismatch { what: "format", .. })
+        ));
+
+        db.drop_collection("users")?;
+
+        assert!(db.collections()?.is_empty());
+        assert!(db.collection::<u32>("users").is_ok());
+
+        Ok(())
+    }
+
+    /// Test that collections opened through separate handles are all kept.
+    #[test]
+    fn test_handles() -> Result<()>
+    {
+        let options = Options::new().backend(MemoryBackend::new());
+        let mut db = Database::open_with("db", options.clone())?;
+        let mut other = Database::open_with("db", options.clone())?;
+
+        db.collection::<String>("one")?;
+        other.tagged_collection::<String, u32>("two", "count")?;
+
+        assert_eq!(db.collections()?, vec!["one", "two"]);
+        assert_eq!(
+            db.collection_info("two")?.and_then(|manifest| manifest.tag),
+            Some(String::from("count"))
+        );
+
+        other.drop_collection("one")?;
+
+        assert_eq!(db.collections()?, vec!["two"]);
+        assert_eq!(db.collection_info("one")?, None);
+
+        Ok(())
+    }
+}
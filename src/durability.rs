@@ -0,0 +1,17 @@
+/// How hard a write tries to make sure the data reached the disk.
+///
+/// Every write goes through a temporary file that is renamed over the target,
+/// so a reader never observes a half-written file. The durability level only
+/// decides what survives a power loss.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Durability
+{
+    /// Leave flushing to the operating system.
+    None,
+    /// Flush the file contents to disk before renaming it into place.
+    Flush,
+    /// Flush the file contents and its metadata, then flush the directory so
+    /// the rename itself is persisted.
+    #[default]
+    Full,
+}
@@ -0,0 +1,192 @@
+use super::{error::Result, key::StoreKey, Format, Json, Options, Stored};
+use serde::{Deserialize, Serialize};
+use std::{collections::btree_map, path::PathBuf};
+
+/// A view into a single entry of a store, which may either be occupied or
+/// vacant.
+///
+/// Returned by [`Store::entry`](super::Store::entry). Every mutation is
+/// written to disk before it returns.
+///
+/// # Example
+///
+/// ```no_run
+/// use store::Store;
+///
+/// # fn main() -> store::Result<()> {
+/// let mut store: Store<String, u32> = Store::new("data")?;
+///
+/// store.entry("visits")?.and_modify(|visits| *visits += 1)?.or_insert(1)?;
+/// # Ok(())
+/// # }
+/// ```
+pub enum Entry<'a, K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// An entry that exists in the store.
+    Occupied(OccupiedEntry<'a, K, T, F>),
+    /// An entry that does not exist in the store.
+    Vacant(VacantEntry<'a, K, T, F>),
+}
+
+impl<'a, K, T, F> Entry<'a, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Get the key of the entry.
+    pub fn key(&self) -> &K
+    {
+        match self
+        {
+            Self::Occupied(entry) => entry.key(),
+            Self::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Save `default` if the entry is vacant, and get the value.
+    pub fn or_insert(self, default: T) -> Result<&'a T>
+    {
+        self.or_insert_with(|| default)
+    }
+
+    /// Save the value computed by `default` if the entry is vacant, and get
+    /// the value.
+    pub fn or_insert_with<D>(self, default: D) -> Result<&'a T>
+    where
+        D: FnOnce() -> T,
+    {
+        match self
+        {
+            Self::Occupied(entry) => Ok(entry.into_ref()),
+            Self::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Save the default value if the entry is vacant, and get the value.
+    pub fn or_default(self) -> Result<&'a T>
+    where
+        T: Default,
+    {
+        self.or_insert_with(T::default)
+    }
+
+    /// Modify and save the value if the entry is occupied.
+    pub fn and_modify<M>(self, f: M) -> Result<Self>
+    where
+        M: FnOnce(&mut T),
+    {
+        match self
+        {
+            Self::Occupied(mut entry) =>
+            {
+                entry.modify(f)?;
+                Ok(Self::Occupied(entry))
+            }
+            Self::Vacant(entry) => Ok(Self::Vacant(entry)),
+        }
+    }
+}
+
+/// A view into an entry that exists in the store.
+pub struct OccupiedEntry<'a, K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The entry in the store's data.
+    pub(super) entry: btree_map::OccupiedEntry<'a, K, Stored<T, F>>,
+}
+
+impl<'a, K, T, F> OccupiedEntry<'a, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Get the key of the entry.
+    pub fn key(&self) -> &K
+    {
+        self.entry.key()
+    }
+
+    /// Get the value.
+    pub fn get(&self) -> &T
+    {
+        self.entry.get().value()
+    }
+
+    /// Convert the entry into a reference to its value.
+    pub fn into_ref(self) -> &'a T
+    {
+        self.entry.into_mut().value()
+    }
+
+    /// Save a new value, returning the previous one.
+    pub fn insert(&mut self, value: T) -> Result<T>
+    {
+        self.entry.get_mut().replace(value)
+    }
+
+    /// Modify and save the value.
+    pub fn modify<M>(&mut self, f: M) -> Result<()>
+    where
+        M: FnOnce(&mut T),
+    {
+        self.entry.get_mut().modify(f)
+    }
+
+    /// Delete the entry from the store, returning its value.
+    pub fn remove(self) -> Result<T>
+    {
+        self.entry.get().delete()?;
+
+        Ok(self.entry.remove().into_value())
+    }
+}
+
+/// A view into an entry that does not exist in the store.
+pub struct VacantEntry<'a, K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The entry in the store's data.
+    pub(super) entry: btree_map::VacantEntry<'a, K, Stored<T, F>>,
+    /// The path to the file that will back the entry.
+    pub(super) path: PathBuf,
+    /// The insertion order the entry will get.
+    pub(super) sequence: u64,
+    /// The options of the store.
+    pub(super) options: &'a Options<F>,
+}
+
+impl<'a, K, T, F> VacantEntry<'a, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Get the key of the entry.
+    pub fn key(&self) -> &K
+    {
+        self.entry.key()
+    }
+
+    /// Save a value, and get a reference to it.
+    pub fn insert(self, value: T) -> Result<&'a T>
+    {
+        let mut stored = Stored::with_value(self.path, value, self.options);
+
+        stored.sequence = self.sequence;
+        stored.save()?;
+
+        Ok(self.entry.insert(stored).value())
+    }
+}
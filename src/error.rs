@@ -0,0 +1,308 @@
+use super::{FormatError, Version};
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+/// A result with a [`StoreError`] as the error type.
+pub type Result<T, E = StoreError> = std::result::Result<T, E>;
+
+/// The filesystem operation that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation
+{
+    /// Opening or reading a file.
+    Read,
+    /// Creating or writing a file.
+    Write,
+    /// Flushing a file or directory to disk.
+    Sync,
+    /// Renaming or linking a file into place.
+    Rename,
+    /// Removing a file or directory.
+    Remove,
+    /// Listing or creating a directory.
+    Directory,
+    /// Opening or locking a lock file.
+    Lock,
+}
+
+impl Display for Operation
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        let name = match self
+        {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Sync => "sync",
+            Self::Rename => "rename",
+            Self::Remove => "remove",
+            Self::Directory => "access directory",
+            Self::Lock => "lock",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// An error returned by a store.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StoreError
+{
+    /// The entry does not exist.
+    NotFound
+    {
+        /// The path to the entry.
+        path: PathBuf,
+    },
+    /// The entry already exists.
+    AlreadyExists
+    {
+        /// The path to the entry.
+        path: PathBuf,
+    },
+    /// The entry exists but could not be deserialized.
+    Corrupt
+    {
+        /// The path to the entry.
+        path: PathBuf,
+        /// The underlying deserialization error.
+        source: FormatError,
+        /// The line the error occurred on, or zero if unknown.
+        line: usize,
+        /// The column the error occurred at, or zero if unknown.
+        column: usize,
+    },
+    /// The value could not be serialized.
+    Serialize
+    {
+        /// The path to the entry.
+        path: PathBuf,
+        /// The underlying serialization error.
+        source: FormatError,
+    },
+    /// A filesystem operation failed.
+    Io
+    {
+        /// The path the operation was performed on.
+        path: PathBuf,
+        /// The operation that failed.
+        op: Operation,
+        /// The underlying I/O error.
+        source: io::Error,
+    },
+    /// The key cannot be used to name an entry.
+    InvalidKey
+    {
+        /// The rejected key.
+        key: String,
+        /// Why the key was rejected.
+        reason: &'static str,
+    },
+    /// The entry or store is locked by someone else.
+    Locked
+    {
+        /// The path to the locked entry or store.
+        path: PathBuf,
+    },
+    /// The store was opened read-only.
+    ReadOnly
+    {
+        /// The path that would have been written.
+        path: PathBuf,
+    },
+    /// The collection was created with a different type or format than the
+    /// one it is opened with.
+    Mismatch
+    {
+        /// The path to the collection.
+        path: PathBuf,
+        /// What does not match, such as `tag` or `format`.
+        what: &'static str,
+        /// What the collection is opened with.
+        expected: String,
+        /// What the collection was created with.
+        found: String,
+    },
+    /// The entry was changed on disk since the version it was expected at.
+    Conflict
+    {
+        /// The path to the entry.
+        path: PathBuf,
+        /// The version found on disk, or `None` if the entry does not exist.
+        found: Option<Version>,
+    },
+    /// The value could not be migrated to the current schema version.
+    Migration
+    {
+        /// The path to the entry.
+        path: PathBuf,
+        /// The schema version the migration failed at.
+        version: u32,
+        /// Why the migration failed.
+        source: FormatError,
+    },
+    /// Any other error, such as one raised by user code.
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl StoreError
+{
+    /// Create an error for a failed filesystem operation.
+    ///
+    /// Missing and already existing files are reported as
+    /// [`StoreError::NotFound`] and [`StoreError::AlreadyExists`].
+    pub(crate) fn io(path: &Path, op: Operation, source: io::Error) -> Self
+    {
+        let path = path.to_path_buf();
+
+        match source.kind()
+        {
+            ErrorKind::NotFound => Self::NotFound { path },
+            ErrorKind::AlreadyExists => Self::AlreadyExists { path },
+            _ => Self::Io { path, op, source },
+        }
+    }
+
+    /// Create an error for a file that could not be deserialized.
+    pub(crate) fn corrupt(path: &Path, source: FormatError) -> Self
+    {
+        Self::Corrupt {
+            path: path.to_path_buf(),
+            line: source.line(),
+            column: source.column(),
+            source,
+        }
+    }
+
+    /// Create an error for a value that could not be serialized.
+    pub(crate) fn serialize(path: &Path, source: FormatError) -> Self
+    {
+        Self::Serialize {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// Get the path the error relates to, if any.
+    pub fn path(&self) -> Option<&Path>
+    {
+        match self
+        {
+            Self::NotFound { path }
+            | Self::AlreadyExists { path }
+            | Self::Corrupt { path, .. }
+            | Self::Serialize { path, .. }
+            | Self::Io { path, .. }
+            | Self::Locked { path }
+            | Self::ReadOnly { path }
+            | Self::Mismatch { path, .. }
+            | Self::Conflict { path, .. }
+            | Self::Migration { path, .. } => Some(path),
+            Self::InvalidKey { .. } | Self::Other(_) => None,
+        }
+    }
+}
+
+impl Display for StoreError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Self::NotFound { path } => write!(f, "{} does not exist", path.display()),
+            Self::AlreadyExists { path } => write!(f, "{} already exists", path.display()),
+            Self::Corrupt {
+                path, line, column, ..
+            } => write!(
+                f,
+                "{} is corrupt at line {} column {}",
+                path.display(),
+                line,
+                column
+            ),
+            Self::Serialize { path, .. } => write!(f, "failed to serialize {}", path.display()),
+            Self::Io { path, op, .. } => write!(f, "failed to {} {}", op, path.display()),
+            Self::InvalidKey { key, reason } => write!(f, "invalid key {:?}: {}", key, reason),
+            Self::Locked { path } => write!(f, "{} is locked", path.display()),
+            Self::ReadOnly { path } => write!(f, "{} is opened read-only", path.display()),
+            Self::Mismatch {
+                path,
+                what,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} was created with {} {}, not {}",
+                path.display(),
+                what,
+                found,
+                expected
+            ),
+            Self::Conflict { path, .. } => write!(f, "{} was changed concurrently", path.display()),
+            Self::Migration { path, version, .. } => write!(
+                f,
+                "failed to migrate {} from schema version {}",
+                path.display(),
+                version
+            ),
+            Self::Other(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for StoreError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)>
+    {
+        match self
+        {
+            Self::Corrupt { source, .. }
+            | Self::Serialize { source, .. }
+            | Self::Migration { source, .. } => Some(source),
+            Self::Io { source, .. } => Some(source),
+            Self::Other(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "anyhow")]
+impl From<anyhow::Error> for StoreError
+{
+    fn from(error: anyhow::Error) -> Self
+    {
+        Self::Other(error.into())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Test that I/O errors are mapped onto the matching variants.
+    #[test]
+    fn test_io()
+    {
+        let path = Path::new("data/value.json");
+
+        let error = StoreError::io(path, Operation::Read, ErrorKind::NotFound.into());
+        assert!(matches!(error, StoreError::NotFound { .. }));
+
+        let error = StoreError::io(path, Operation::Write, ErrorKind::PermissionDenied.into());
+        assert!(matches!(
+            error,
+            StoreError::Io {
+                op: Operation::Write,
+                ..
+            }
+        ));
+        assert_eq!(error.path(), Some(path));
+        assert_eq!(error.to_string(), "failed to write data/value.json");
+    }
+}
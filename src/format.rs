@@ -0,0 +1,303 @@
+use super::migration;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+};
+
+/// A serialization format used to store values on disk.
+pub trait Format: Clone
+{
+    /// The name of the format, recorded in the manifest of every store using
+    /// it. It must never change, since opening a store fails if it differs.
+    const NAME: &'static str;
+
+    /// The file extension of stored values, without the leading dot.
+    fn extension(&self) -> &str;
+
+    /// Serialize a value.
+    fn serialize<T>(&self, value: &T) -> Result<Vec<u8>, FormatError>
+    where
+        T: Serialize;
+
+    /// Deserialize a value.
+    fn deserialize<T>(&self, bytes: &[u8]) -> Result<T, FormatError>
+    where
+        T: DeserializeOwned;
+}
+
+/// Compact JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Json;
+
+impl Format for Json
+{
+    const NAME: &'static str = "json";
+
+    fn extension(&self) -> &str
+    {
+        "json"
+    }
+
+    fn serialize<T>(&self, value: &T) -> Result<Vec<u8>, FormatError>
+    where
+        T: Serialize,
+    {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn deserialize<T>(&self, bytes: &[u8]) -> Result<T, FormatError>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Indented, human-editable JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PrettyJson;
+
+impl Format for PrettyJson
+{
+    const NAME: &'static str = "pretty-json";
+
+    fn extension(&self) -> &str
+    {
+        "json"
+    }
+
+    fn serialize<T>(&self, value: &T) -> Result<Vec<u8>, FormatError>
+    where
+        T: Serialize,
+    {
+        let mut bytes = serde_json::to_vec_pretty(value)?;
+        bytes.push(b'\n');
+        Ok(bytes)
+    }
+
+    fn deserialize<T>(&self, bytes: &[u8]) -> Result<T, FormatError>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Line-delimited JSON.
+///
+/// Only sequences can be stored, with one compact JSON element per line. Any
+/// other value fails to serialize, since it could not be told apart from a
+/// sequence holding just that value when read back. A value with a schema
+/// version is written as a single line holding its envelope, see
+/// [`migration`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct JsonLines;
+
+impl Format for JsonLines
+{
+    const NAME: &'static str = "json-lines";
+
+    fn extension(&self) -> &str
+    {
+        "jsonl"
+    }
+
+    fn serialize<T>(&self, value: &T) -> Result<Vec<u8>, FormatError>
+    where
+        T: Serialize,
+    {
+        let lines = match serde_json::to_value(value)?
+        {
+            Value::Array(elements)
+                if elements.len() == 1 && migration::is_sequence_envelope(&elements[0]) =>
+            {
+                return Err(FormatError::new(
+                    "JSON Lines cannot store a sequence holding just an envelope",
+                ));
+            }
+            Value::Array(elements) => elements,
+            value if migration::is_sequence_envelope(&value) => vec![value],
+            _ => return Err(FormatError::new("JSON Lines can only store sequences")),
+        };
+
+        let mut bytes = Vec::new();
+
+        for line in lines
+        {
+            serde_json::to_writer(&mut bytes, &line)?;
+            bytes.push(b'\n');
+        }
+
+        Ok(bytes)
+    }
+
+    fn deserialize<T>(&self, bytes: &[u8]) -> Result<T, FormatError>
+    where
+        T: DeserializeOwned,
+    {
+        let mut lines = Vec::new();
+
+        for (index, line) in bytes.split(|byte| *byte == b'\n').enumerate()
+        {
+            if line.iter().all(u8::is_ascii_whitespace)
+            {
+                continue;
+            }
+
+            let value: Value = serde_json::from_slice(line).map_err(|error| {
+                let column = error.column();
+                FormatError::from(error).at(index + 1, column)
+            })?;
+
+            lines.push(value);
+        }
+
+        if lines.len() == 1 && migration::is_sequence_envelope(&lines[0])
+        {
+            return Ok(serde_json::from_value(lines.remove(0))?);
+        }
+
+        Ok(serde_json::from_value(Value::Array(lines))?)
+    }
+}
+
+/// An error raised while serializing or deserializing a value.
+pub struct FormatError
+{
+    /// The underlying error.
+    source: Box<dyn Error + Send + Sync>,
+    /// The line the error occurred on, or zero if unknown.
+    line: usize,
+    /// The column the error occurred at, or zero if unknown.
+    column: usize,
+}
+
+impl FormatError
+{
+    /// Create a new format error without position information.
+    pub fn new<E>(error: E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        Self {
+            source: error.into(),
+            line: 0,
+            column: 0,
+        }
+    }
+
+    /// Set the position the error occurred at.
+    pub fn at(mut self, line: usize, column: usize) -> Self
+    {
+        self.line = line;
+        self.column = column;
+        self
+    }
+
+    /// Get the line the error occurred on, or zero if unknown.
+    pub fn line(&self) -> usize
+    {
+        self.line
+    }
+
+    /// Get the column the error occurred at, or zero if unknown.
+    pub fn column(&self) -> usize
+    {
+        self.column
+    }
+}
+
+impl From<serde_json::Error> for FormatError
+{
+    fn from(error: serde_json::Error) -> Self
+    {
+        let (line, column) = (error.line(), error.column());
+        Self::new(error).at(line, column)
+    }
+}
+
+impl Debug for FormatError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        Debug::fmt(&self.source, f)
+    }
+}
+
+impl Display for FormatError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        Display::fmt(&self.source, f)
+    }
+}
+
+impl Error for FormatError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)>
+    {
+        Some(&*self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use serde_json::json;
+
+    /// Test that every format round-trips values.
+    #[test]
+    fn test_round_trip()
+    {
+        let value = vec![String::from("hello"), String::from("world")];
+
+        let bytes = Json.serialize(&value).unwrap();
+        assert_eq!(bytes, br#"["hello","world"]"#);
+        assert_eq!(Json.deserialize::<Vec<String>>(&bytes).unwrap(), value);
+
+        let bytes = PrettyJson.serialize(&value).unwrap();
+        assert_eq!(bytes, b"[\n  \"hello\",\n  \"world\"\n]\n");
+        assert_eq!(
+            PrettyJson.deserialize::<Vec<String>>(&bytes).unwrap(),
+            value
+        );
+
+        let bytes = JsonLines.serialize(&value).unwrap();
+        assert_eq!(bytes, b"\"hello\"\n\"world\"\n");
+        assert_eq!(JsonLines.deserialize::<Vec<String>>(&bytes).unwrap(), value);
+
+        let bytes = JsonLines.serialize(&vec![5]).unwrap();
+        assert_eq!(bytes, b"5\n");
+        assert_eq!(JsonLines.deserialize::<Value>(&bytes).unwrap(), json!([5]));
+    }
+
+    /// Test that line-delimited JSON refuses values it could not read back.
+    #[test]
+    fn test_json_lines_shapes()
+    {
+        assert!(JsonLines.serialize(&5).is_err());
+        assert!(JsonLines.serialize(&json!(5)).is_err());
+        assert!(JsonLines.serialize(&String::from("hello")).is_err());
+
+        let envelope = json!({ "$version": 1, "$data": [5] });
+        let bytes = JsonLines.serialize(&envelope).unwrap();
+
+        assert_eq!(bytes, b"{\"$data\":[5],\"$version\":1}\n");
+        assert_eq!(JsonLines.deserialize::<Value>(&bytes).unwrap(), envelope);
+        assert!(JsonLines.serialize(&json!([envelope])).is_err());
+    }
+
+    /// Test that line-delimited JSON reports the line an error occurred on.
+    #[test]
+    fn test_json_lines_error()
+    {
+        let error = JsonLines
+            .deserialize::<Vec<u32>>(b"1\n2\n{ not json\n")
+            .unwrap_err();
+
+        assert_eq!(error.line(), 3);
+    }
+}
@@ -0,0 +1,159 @@
+use super::{error::Result, Format, Json, Stored};
+use serde::{Deserialize, Serialize};
+use std::{
+    ops::{Deref, DerefMut},
+    thread,
+};
+
+/// A mutable reference to a stored value that saves it when dropped.
+///
+/// Returned by [`Store::get_mut`](super::Store::get_mut) and
+/// [`Stored::get_mut`]. The value is only written if it was mutably
+/// accessed. Errors while saving on drop are ignored, so use
+/// [`RefMut::commit`] to find out whether the write succeeded. A guard
+/// dropped while its thread panics does not save, so a modification cut short
+/// by the panic never reaches the file.
+///
+/// # Example
+///
+/// ```no_run
+/// use store::Store;
+///
+/// # fn main() -> store::Result<()> {
+/// let mut store: Store<String, Vec<String>> = Store::new("data")?;
+///
+/// if let Some(mut names) = store.get_mut("names")
+/// {
+///     names.push(String::from("Milan"));
+///     names.commit()?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct RefMut<'a, T, F = Json>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The stored value being modified.
+    stored: &'a mut Stored<T, F>,
+    /// Whether the value was mutably accessed since it was last saved.
+    dirty: bool,
+}
+
+impl<'a, T, F> RefMut<'a, T, F>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Create a guard over a stored value.
+    pub(super) fn new(stored: &'a mut Stored<T, F>) -> Self
+    {
+        Self {
+            stored,
+            dirty: false,
+        }
+    }
+
+    /// Check whether the value was modified since it was last saved.
+    pub fn is_dirty(&self) -> bool
+    {
+        self.dirty
+    }
+
+    /// Save the value if it was modified, returning any error.
+    ///
+    /// A failed save is not retried when the guard is dropped.
+    pub fn commit(mut self) -> Result<()>
+    {
+        let result = self.save();
+        self.dirty = false;
+        result
+    }
+
+    /// Save the value if it was modified.
+    fn save(&mut self) -> Result<()>
+    {
+        if self.dirty
+        {
+            self.stored.save()?;
+            self.dirty = false;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T, F> Deref for RefMut<'_, T, F>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T
+    {
+        &self.stored.value
+    }
+}
+
+impl<T, F> DerefMut for RefMut<'_, T, F>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    fn deref_mut(&mut self) -> &mut T
+    {
+        self.dirty = true;
+        self.stored.modified = true;
+        &mut self.stored.value
+    }
+}
+
+impl<T, F> Drop for RefMut<'_, T, F>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    fn drop(&mut self)
+    {
+        if !thread::panicking()
+        {
+            let _ = self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{Backend, MemoryBackend, Options};
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        path::Path,
+    };
+
+    /// Test that a guard dropped during a panic does not save.
+    #[test]
+    fn test_panic() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut stored = Stored::load_or_else_with("list", &options, || vec![1, 2])?;
+
+        stored.save()?;
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut guard = stored.get_mut();
+
+            guard.clear();
+            panic!("interrupted");
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(backend.read(Path::new("list.json"))?, b"[1,2]");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+use super::{error::Result, key::StoreKey, Format, Json, Stored};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{btree_map, BTreeMap},
+    iter::FusedIterator,
+    vec,
+};
+
+/// An iterator over the keys and values of a store, ordered by key.
+///
+/// Returned by [`Store::iter`](super::Store::iter).
+pub struct Iter<'a, K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The entries of the store.
+    pub(super) inner: btree_map::Iter<'a, K, Stored<T, F>>,
+}
+
+impl<'a, K, T, F> Iterator for Iter<'a, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    type Item = (&'a K, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        self.inner.next().map(|(key, stored)| (key, stored.value()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, T, F> ExactSizeIterator for Iter<'_, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+}
+
+impl<K, T, F> FusedIterator for Iter<'_, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+}
+
+/// An iterator over the keys of a store.
+///
+/// Returned by [`Store::keys`](super::Store::keys).
+pub struct Keys<'a, K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The entries of the store.
+    pub(super) inner: Iter<'a, K, T, F>,
+}
+
+impl<'a, K, T, F> Iterator for Keys<'a, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, T, F> ExactSizeIterator for Keys<'_, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+}
+
+impl<K, T, F> FusedIterator for Keys<'_, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+}
+
+/// An iterator over the values of a store.
+///
+/// Returned by [`Store::values`](super::Store::values).
+pub struct Values<'a, K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The entries of the store.
+    pub(super) inner: Iter<'a, K, T, F>,
+}
+
+impl<'a, K, T, F> Iterator for Values<'a, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, T, F> ExactSizeIterator for Values<'_, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+}
+
+impl<K, T, F> FusedIterator for Values<'_, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+}
+
+/// An owning iterator over the keys and values of a store.
+///
+/// Returned by the [`IntoIterator`] implementation of
+/// [`Store`](super::Store). The files on disk are left untouched.
+pub struct IntoIter<K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The entries of the store.
+    pub(super) inner: btree_map::IntoIter<K, Stored<T, F>>,
+}
+
+impl<K, T, F> Iterator for IntoIter<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    type Item = (K, T);
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        self.inner
+            .next()
+            .map(|(key, stored)| (key, stored.into_value()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, T, F> ExactSizeIterator for IntoIter<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+}
+
+/// A draining iterator over the keys and values of a store.
+///
+/// Returned by [`Store::drain`](super::Store::drain). Every entry is deleted
+/// from disk as it is yielded. An entry whose file cannot be deleted is
+/// yielded as an error and kept in the store, as are the entries that were
+/// not yet yielded when the iterator is dropped.
+pub struct Drain<'a, K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The entries of the store.
+    pub(super) data: &'a mut BTreeMap<K, Stored<T, F>>,
+    /// The keys of the entries left to drain.
+    pub(super) keys: vec::IntoIter<K>,
+}
+
+impl<K, T, F> Iterator for Drain<'_, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    type Item = Result<(K, T)>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        let key = self.keys.next()?;
+
+        if let Err(error) = self.data.get(&key)?.delete()
+        {
+            return Some(Err(error));
+        }
+
+        let stored = self.data.remove(&key)?;
+
+        Some(Ok((key, stored.into_value())))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        self.keys.size_hint()
+    }
+}
+
+impl<K, T, F> ExactSizeIterator for Drain<'_, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+}
@@ -0,0 +1,453 @@
+//! Encoding of entry names into safe file names.
+//!
+//! Every key type implements [`StoreKey`], which turns a key into the stem of
+//! its file name and back. String names are percent-encoded so that any
+//! non-empty string can be used as the name of an entry without escaping the
+//! store's directory. ASCII letters, digits, `-`, `_`, `.` and spaces are kept
+//! as they are, every other byte is written as `%XX`. A leading or trailing
+//! `.` or space and the first character of names reserved on Windows (such as
+//! `CON` or `nul`) are encoded as well.
+
+use super::error::{Result, StoreError};
+
+/// The maximum length of an encoded name, leaving room for an extension
+/// within the common limit of 255 bytes per file name.
+const MAX_LEN: usize = 240;
+
+/// The separator between the components of a tuple key.
+const SEPARATOR: char = ',';
+
+/// A type that can be used as the key of a store.
+///
+/// A key is encoded into the stem of its file name, and decoded back when the
+/// store's directory is scanned. Decoding must reject any file name that
+/// encoding would not produce, so that foreign files are skipped rather than
+/// loaded under the wrong key. The store additionally refuses encoded names
+/// that are empty, hidden, too long or contain a path separator.
+///
+/// Keys are implemented for [`String`], the integer types and tuples of up to
+/// four keys. Tuple components are joined by `,`, which string components
+/// always escape. Wrap a tuple in [`Nested`] to store each component in a
+/// nested directory instead.
+///
+/// # Example
+///
+/// ```
+/// use store::{key::StoreKey, Result};
+///
+/// #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// struct UserId(u64);
+///
+/// impl StoreKey for UserId
+/// {
+///     fn encode(&self) -> Result<String>
+///     {
+///         self.0.encode()
+///     }
+///
+///     fn decode(file_name: &str) -> Result<Self>
+///     {
+///         u64::decode(file_name).map(UserId)
+///     }
+/// }
+///
+/// assert_eq!(UserId(42).encode().unwrap(), "42");
+/// assert!(UserId::decode("042").is_err());
+/// ```
+pub trait StoreKey: Ord + Clone
+{
+    /// The number of path segments an encoded key spans, separated by `/`.
+    /// Every segment but the last is a directory inside the store.
+    const SEGMENTS: usize = 1;
+
+    /// Encode the key into a file name, without extension.
+    fn encode(&self) -> Result<String>;
+
+    /// Decode a file name, without extension, back into a key.
+    fn decode(file_name: &str) -> Result<Self>;
+}
+
+impl StoreKey for String
+{
+    fn encode(&self) -> Result<String>
+    {
+        encode(self)
+    }
+
+    fn decode(file_name: &str) -> Result<Self>
+    {
+        decode(file_name)
+    }
+}
+
+/// Implement [`StoreKey`] for integer types, which are written in decimal.
+macro_rules! integer_key {
+    ($($ty:ty),*) => {
+        $(
+            impl StoreKey for $ty
+            {
+                fn encode(&self) -> Result<String>
+                {
+                    Ok(self.to_string())
+                }
+
+                fn decode(file_name: &str) -> Result<Self>
+                {
+                    match file_name.parse::<$ty>()
+                    {
+                        Ok(key) if key.to_string() == file_name => Ok(key),
+                        _ => Err(invalid(file_name, "not a decimal integer")),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integer_key!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// Implement [`StoreKey`] for tuples of keys, joining their components.
+macro_rules! tuple_key {
+    ($len:literal => $($name:ident . $index:tt),*) => {
+        impl<$($name),*> StoreKey for ($($name,)*)
+        where
+            $($name: StoreKey),*
+        {
+            fn encode(&self) -> Result<String>
+            {
+                let parts = [$(self.$index.encode()?),*];
+
+                if parts.iter().any(|part| part.contains(SEPARATOR))
+                {
+                    return Err(invalid(
+                        &parts.join(","),
+                        "tuple components cannot contain `,` once encoded",
+                    ));
+                }
+
+                Ok(parts.join(","))
+            }
+
+            fn decode(file_name: &str) -> Result<Self>
+            {
+                let parts: Vec<_> = file_name.split(SEPARATOR).collect();
+
+                if parts.len() != $len
+                {
+                    return Err(invalid(file_name, "wrong number of tuple components"));
+                }
+
+                Ok(($($name::decode(parts[$index])?,)*))
+            }
+        }
+    };
+}
+
+tuple_key!(1 => A.0);
+tuple_key!(2 => A.0, B.1);
+tuple_key!(3 => A.0, B.1, C.2);
+tuple_key!(4 => A.0, B.1, C.2, D.3);
+
+/// A tuple key whose components are stored in nested directories.
+///
+/// The key `Nested((String::from("users"), 42))` is stored as `users/42`
+/// inside the store, so large stores can be organised into a tree. Components
+/// are joined by `/`, which string components always escape.
+///
+/// # Example
+///
+/// ```no_run
+/// use store::{Nested, Store};
+///
+/// # fn main() -> store::Result<()> {
+/// let mut store: Store<Nested<(String, u32)>, String> = Store::new("data")?;
+///
+/// store.save((String::from("users"), 42), String::from("Milan"))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Nested<T>(pub T);
+
+impl<T> From<T> for Nested<T>
+{
+    fn from(key: T) -> Self
+    {
+        Self(key)
+    }
+}
+
+/// Implement [`StoreKey`] for nested tuples of keys, one directory per
+/// component but the last.
+macro_rules! nested_key {
+    ($len:literal => $($name:ident . $index:tt),*) => {
+        impl<$($name),*> StoreKey for Nested<($($name,)*)>
+        where
+            $($name: StoreKey),*
+        {
+            const SEGMENTS: usize = $len;
+
+            fn encode(&self) -> Result<String>
+            {
+                let parts = [$((self.0).$index.encode()?),*];
+
+                if parts.iter().any(|part| part.contains('/'))
+                {
+                    return Err(invalid(
+                        &parts.join("/"),
+                        "nested components cannot contain `/` once encoded",
+                    ));
+                }
+
+                Ok(parts.join("/"))
+            }
+
+            fn decode(file_name: &str) -> Result<Self>
+            {
+                let parts: Vec<_> = file_name.split('/').collect();
+
+                if parts.len() != $len
+                {
+                    return Err(invalid(file_name, "wrong number of nested components"));
+                }
+
+                Ok(Nested(($($name::decode(parts[$index])?,)*)))
+            }
+        }
+    };
+}
+
+nested_key!(2 => A.0, B.1);
+nested_key!(3 => A.0, B.1, C.2);
+nested_key!(4 => A.0, B.1, C.2, D.3);
+
+/// Names that cannot be used as file names on Windows, regardless of case or
+/// extension.
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Encode an entry name into a file name, without extension.
+///
+/// Fails with [`StoreError::InvalidKey`] if the name is empty or too long.
+///
+/// # Example
+///
+/// ```
+/// use store::key;
+///
+/// assert_eq!(key::encode("../etc/passwd").unwrap(), "%2E.%2Fetc%2Fpasswd");
+/// assert_eq!(key::decode("%2E.%2Fetc%2Fpasswd").unwrap(), "../etc/passwd");
+/// ```
+pub fn encode(name: &str) -> Result<String>
+{
+    if name.is_empty()
+    {
+        return Err(invalid(name, "keys cannot be empty"));
+    }
+
+    let bytes = name.as_bytes();
+    let reserved = is_reserved(name);
+    let mut encoded = String::with_capacity(bytes.len());
+
+    for (index, &byte) in bytes.iter().enumerate()
+    {
+        let edge = index == 0 || index == bytes.len() - 1;
+        let keep = match byte
+        {
+            b'.' | b' ' => !edge,
+            b'-' | b'_' => true,
+            _ => byte.is_ascii_alphanumeric() && !(index == 0 && reserved),
+        };
+
+        if keep
+        {
+            encoded.push(byte as char);
+        }
+        else
+        {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    if encoded.len() > MAX_LEN
+    {
+        return Err(invalid(
+            name,
+            "keys cannot be longer than 240 bytes once encoded",
+        ));
+    }
+
+    Ok(encoded)
+}
+
+/// Decode a file name, without extension, back into an entry name.
+///
+/// Fails with [`StoreError::InvalidKey`] if the file name was not produced by
+/// [`encode`].
+pub fn decode(file_name: &str) -> Result<String>
+{
+    let mut bytes = Vec::with_capacity(file_name.len());
+    let mut chars = file_name.bytes();
+
+    while let Some(byte) = chars.next()
+    {
+        if byte != b'%'
+        {
+            bytes.push(byte);
+            continue;
+        }
+
+        let high = chars.next().and_then(|byte| (byte as char).to_digit(16));
+        let low = chars.next().and_then(|byte| (byte as char).to_digit(16));
+
+        match (high, low)
+        {
+            (Some(high), Some(low)) => bytes.push((high * 16 + low) as u8),
+            _ => return Err(invalid(file_name, "malformed percent-encoding")),
+        }
+    }
+
+    let name = String::from_utf8(bytes).map_err(|_| invalid(file_name, "not valid UTF-8"))?;
+
+    if encode(&name).ok().as_deref() != Some(file_name)
+    {
+        return Err(invalid(file_name, "not an encoded key"));
+    }
+
+    Ok(name)
+}
+
+/// Check that an encoded path segment is safe to use as a file or directory
+/// name inside a store.
+///
+/// Fails with [`StoreError::InvalidKey`] if it is empty, too long, hidden or
+/// not a single path component.
+pub(crate) fn check(file_name: &str) -> Result<()>
+{
+    if file_name.is_empty()
+    {
+        return Err(invalid(file_name, "keys cannot be empty"));
+    }
+
+    if file_name.len() > MAX_LEN
+    {
+        return Err(invalid(
+            file_name,
+            "keys cannot be longer than 240 bytes once encoded",
+        ));
+    }
+
+    if file_name.starts_with('.')
+    {
+        return Err(invalid(
+            file_name,
+            "keys cannot start with `.` once encoded",
+        ));
+    }
+
+    if file_name.contains(['/', '\\', '\0'])
+    {
+        return Err(invalid(
+            file_name,
+            "keys cannot contain path separators once encoded",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check whether a name would be reserved on Windows once used as a file
+/// name.
+fn is_reserved(name: &str) -> bool
+{
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+
+    RESERVED
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+/// Create an error for an invalid key.
+fn invalid(key: &str, reason: &'static str) -> StoreError
+{
+    StoreError::InvalidKey {
+        key: key.to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Test that names are encoded into safe file names and decoded back.
+    #[test]
+    fn test_round_trip()
+    {
+        let cases = [
+            ("hello", "hello"),
+            ("hello.json", "hello.json"),
+            ("../../etc/passwd", "%2E.%2F..%2Fetc%2Fpasswd"),
+            ("/abs/path", "%2Fabs%2Fpath"),
+            ("nul\0byte", "nul%00byte"),
+            ("CON", "%43ON"),
+            ("nul.txt", "%6Eul.txt"),
+            ("console", "console"),
+            (".hidden ", "%2Ehidden%20"),
+            ("100%", "100%25"),
+            ("héllo", "h%C3%A9llo"),
+        ];
+
+        for (name, file_name) in cases
+        {
+            assert_eq!(encode(name).unwrap(), file_name);
+            assert_eq!(decode(file_name).unwrap(), name);
+        }
+    }
+
+    /// Test that invalid names and foreign file names are rejected.
+    #[test]
+    fn test_invalid()
+    {
+        assert!(matches!(encode(""), Err(StoreError::InvalidKey { .. })));
+        assert!(encode(&"a".repeat(241)).is_err());
+        assert!(decode("%zz").is_err());
+        assert!(decode("%FF").is_err());
+        assert!(decode("has space%").is_err());
+        assert!(decode(".hidden").is_err());
+    }
+
+    /// Test that integer and tuple keys are encoded and decoded.
+    #[test]
+    fn test_store_key() -> Result<()>
+    {
+        assert_eq!(42u32.encode()?, "42");
+        assert_eq!(i64::decode("-7")?, -7);
+        assert!(u32::decode("007").is_err());
+        assert!(u32::decode("+7").is_err());
+        assert!(u8::decode("256").is_err());
+
+        let key = (String::from("a,b"), 3u16);
+
+        assert_eq!(key.encode()?, "a%2Cb,3");
+        assert_eq!(<(String, u16)>::decode("a%2Cb,3")?, key);
+        assert!(<(String, u16)>::decode("a,b,3").is_err());
+        assert!(<(String, u16)>::decode("a").is_err());
+
+        assert!(check("../x").is_err());
+        assert!(check(".x").is_err());
+        assert!(check("").is_err());
+        assert!(check("x").is_ok());
+
+        let key = Nested((String::from("users"), 42u32));
+
+        assert_eq!(key.encode()?, "users/42");
+        assert_eq!(Nested::<(String, u32)>::decode("users/42")?, key);
+        assert!(Nested::<(String, u32)>::decode("users,42").is_err());
+
+        Ok(())
+    }
+}
@@ -1,5 +1,47 @@
+pub mod backend;
+pub mod batch;
+pub mod conflict;
+pub mod database;
+pub mod durability;
+pub mod entry;
+pub mod error;
+pub mod format;
+pub mod guard;
+pub mod iter;
+pub mod key;
+pub mod lock;
+pub mod manifest;
+pub mod migration;
+pub mod options;
+pub mod order;
+pub mod scan;
+pub mod shared;
+pub mod snapshot;
 pub mod store;
 pub mod stored;
+pub mod transaction;
+pub mod version;
 
+pub use backend::{Backend, FsBackend, MemoryBackend, Metadata};
+pub use batch::Batch;
+pub use conflict::ConflictPolicy;
+pub use database::Database;
+pub use durability::Durability;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use error::{Operation, Result, StoreError};
+pub use format::{Format, FormatError, Json, JsonLines, PrettyJson};
+pub use guard::RefMut;
+pub use iter::{Drain, IntoIter, Iter, Keys, Values};
+pub use key::{Nested, StoreKey};
+pub use lock::{Lock, LockMode};
+pub use manifest::Manifest;
+pub use migration::Migrations;
+pub use options::Options;
+pub use order::Order;
+pub use scan::{LoadFailure, ScanReport, SkipReason, Skipped};
+pub use shared::SharedStore;
+pub use snapshot::{Snapshot, SnapshotIter};
 pub use store::Store;
 pub use stored::Stored;
+pub use transaction::Transaction;
+pub use version::{Fingerprint, Version};
@@ -0,0 +1,565 @@
+//! Advisory locks on stores and entries, shared between processes.
+//!
+//! A lock is taken on a lock file through the store's [`Backend`]: the store
+//! itself is locked through a hidden file in its directory, and every entry
+//! through a file of its own in a hidden directory next to it. Locks are only
+//! advisory, so they protect against other programs that lock as well but do
+//! not prevent anyone from writing.
+//!
+//! Lock files are never removed, since removing a file another process is
+//! about to lock would let two processes hold the same lock. The lock files of
+//! entries therefore stay in the hidden directory after their entries are
+//! deleted or pruned, one empty file for every key that was ever locked, until
+//! the whole store is removed.
+//!
+//! Every write to a store holds the store's lock exclusively while it lasts,
+//! waiting for it as long as set with
+//! [`Options::lock_timeout`](crate::Options::lock_timeout). Writes and
+//! exclusive store locks taken through the same store share a single hold of
+//! the lock, so a store can write while holding its own exclusive lock, and
+//! threads writing through a [`SharedStore`](crate::SharedStore) do not wait
+//! for each other. Writing while holding the store's own lock shared fails
+//! with [`StoreError::Locked`], since it would wait forever. Writes do not
+//! take entry locks.
+//!
+//! A store opened with [`Options::read_only`](crate::Options::read_only)
+//! holds the store's lock shared only while it reads from disk, such as while
+//! opening or scanning the store or reloading a value, and waits for it as
+//! long as writes do. Readers therefore keep writers out only for the length
+//! of a single read, and the other way around.
+//!
+//! The [`FsBackend`](crate::FsBackend) locks through the operating system,
+//! which releases a lock as soon as the process holding it exits. A holder
+//! that crashed therefore never leaves a stale lock behind, and the lock file
+//! it leaves is simply locked again by the next process.
+
+use super::{
+    error::{Result, StoreError},
+    key::StoreKey,
+    Backend, Format, Options, Store,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    any::Any,
+    cmp,
+    ffi::OsString,
+    fmt::{self, Debug, Formatter},
+    path::{Path, PathBuf},
+    sync::{Arc, LockResult, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The name of the store's lock file inside its directory.
+pub(crate) const FILE_NAME: &str = ".store.lock";
+
+/// The name of the directory holding the lock files of entries.
+pub(crate) const DIR_NAME: &str = ".locks";
+
+/// The longest time to sleep between attempts to take a held lock.
+const MAX_BACKOFF: Duration = Duration::from_millis(50);
+
+/// How a lock is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockMode
+{
+    /// Held by any number of readers at once, as long as no one holds it
+    /// exclusively.
+    Shared,
+    /// Held by a single writer.
+    Exclusive,
+}
+
+/// An advisory lock, released when dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use store::{LockMode, Store};
+///
+/// # fn main() -> store::Result<()> {
+/// let mut store: Store<String, u64> = Store::new("counters")?;
+/// let lock = store.lock_entry("visits", LockMode::Exclusive)?;
+/// let visits = store.get("visits").copied().unwrap_or(0);
+///
+/// store.save("visits", visits + 1)?;
+/// drop(lock);
+/// # Ok(())
+/// # }
+/// ```
+#[must_use = "the lock is released as soon as it is dropped"]
+pub struct Lock
+{
+    /// The path to the lock file.
+    path: PathBuf,
+    /// How the lock is held.
+    mode: LockMode,
+    /// Whatever keeps the lock held, released when dropped.
+    _handle: Box<dyn Any + Send + Sync>,
+}
+
+impl Lock
+{
+    /// Create a lock that is held until `handle` is dropped.
+    ///
+    /// Used by backends implementing [`Backend::try_lock`].
+    pub fn new<H>(path: PathBuf, mode: LockMode, handle: H) -> Self
+    where
+        H: Send + Sync + 'static,
+    {
+        Self {
+            path,
+            mode,
+            _handle: Box::new(handle),
+        }
+    }
+
+    /// Get the path to the lock file.
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    /// Get how the lock is held.
+    pub fn mode(&self) -> LockMode
+    {
+        self.mode
+    }
+}
+
+impl Debug for Lock
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        f.debug_struct("Lock")
+            .field("path", &self.path)
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+/// The lock of a store, shared by the store and its entries.
+#[derive(Debug)]
+pub(crate) struct StoreLock
+{
+    /// The backend the lock file is locked through.
+    backend: Arc<dyn Backend>,
+    /// The path to the store's lock file.
+    path: PathBuf,
+    /// How long writes and reads wait for the lock, or `None` to wait
+    /// forever.
+    timeout: Option<Duration>,
+    /// Whether the store is read-only, so that its reads hold the lock
+    /// shared rather than its writes holding it exclusively.
+    read_only: bool,
+    /// How the store currently holds the lock.
+    state: Mutex<State>,
+}
+
+/// How a store currently holds its lock.
+#[derive(Debug, Default)]
+struct State
+{
+    /// The exclusive lock, held while any write or exclusive lock of the
+    /// store is.
+    exclusive: Option<Lock>,
+    /// The number of writes and exclusive locks sharing `exclusive`.
+    writers: usize,
+    /// The number of shared locks held or being taken.
+    readers: usize,
+}
+
+/// A hold of a store's lock, released when dropped.
+pub(crate) struct Held
+{
+    /// The lock being held.
+    store_lock: Arc<StoreLock>,
+    /// How the lock is held.
+    mode: LockMode,
+    /// The shared lock taken for this hold alone, if held shared.
+    _shared: Option<Lock>,
+}
+
+impl StoreLock
+{
+    /// Create the lock of the store at `dir` opened with `options`.
+    pub(crate) fn new<F>(dir: &Path, options: &Options<F>) -> Self
+    {
+        Self {
+            backend: options.backend.clone(),
+            path: dir.join(FILE_NAME),
+            timeout: options.lock_timeout,
+            read_only: options.read_only,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Hold the lock exclusively, waiting up to `timeout` unless the store
+    /// already holds it exclusively.
+    pub(crate) fn exclusive(self: &Arc<Self>, timeout: Option<Duration>) -> Result<Held>
+    {
+        let mut state = self.state();
+
+        if state.exclusive.is_none()
+        {
+            if state.readers > 0
+            {
+                return Err(self.locked());
+            }
+
+            state.exclusive = Some(acquire(
+                &*self.backend,
+                &self.path,
+                LockMode::Exclusive,
+                timeout,
+            )?);
+        }
+
+        state.writers += 1;
+
+        Ok(Held {
+            store_lock: self.clone(),
+            mode: LockMode::Exclusive,
+            _shared: None,
+        })
+    }
+
+    /// Hold the lock shared, waiting up to `timeout`.
+    pub(crate) fn shared(self: &Arc<Self>, timeout: Option<Duration>) -> Result<Held>
+    {
+        {
+            let mut state = self.state();
+
+            if state.exclusive.is_some()
+            {
+                return Err(self.locked());
+            }
+
+            state.readers += 1;
+        }
+
+        // Counted before waiting, so a write of this store fails rather than
+        // waiting for this lock while holding the state.
+        let mut held = Held {
+            store_lock: self.clone(),
+            mode: LockMode::Shared,
+            _shared: None,
+        };
+
+        held._shared = Some(acquire(
+            &*self.backend,
+            &self.path,
+            LockMode::Shared,
+            timeout,
+        )?);
+
+        Ok(held)
+    }
+
+    /// Get how the store currently holds the lock.
+    fn state(&self) -> MutexGuard<'_, State>
+    {
+        recover(self.state.lock())
+    }
+
+    /// Create the error for a lock this store would wait on itself for.
+    fn locked(&self) -> StoreError
+    {
+        StoreError::Locked {
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl Drop for Held
+{
+    fn drop(&mut self)
+    {
+        let mut state = self.store_lock.state();
+
+        match self.mode
+        {
+            LockMode::Shared => state.readers -= 1,
+            LockMode::Exclusive =>
+            {
+                state.writers -= 1;
+
+                if state.writers == 0
+                {
+                    state.exclusive = None;
+                }
+            }
+        }
+    }
+}
+
+/// Hold the lock of the store a write goes to exclusively, if it belongs to
+/// a writable store.
+pub(crate) fn write(store_lock: Option<&Arc<StoreLock>>) -> Result<Option<Held>>
+{
+    store_lock
+        .filter(|store_lock| !store_lock.read_only)
+        .map(|store_lock| store_lock.exclusive(store_lock.timeout))
+        .transpose()
+}
+
+/// Hold the lock of the store a read goes to shared, if it belongs to a
+/// read-only store.
+pub(crate) fn read(store_lock: Option<&Arc<StoreLock>>) -> Result<Option<Held>>
+{
+    store_lock
+        .filter(|store_lock| store_lock.read_only)
+        .map(|store_lock| store_lock.shared(store_lock.timeout))
+        .transpose()
+}
+
+/// Get the guard of one of the crate's in-process locks, even if a thread
+/// panicked while holding it.
+///
+/// Every such lock guards state that is changed in steps leaving it
+/// consistent, so a panic leaves at most a change that was not written yet,
+/// just like a panic between two calls on a plain store.
+pub(crate) fn recover<G>(result: LockResult<G>) -> G
+{
+    result.unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Take a lock through a backend, waiting up to `timeout` for it to be
+/// released, or forever if `timeout` is `None`.
+///
+/// Fails with [`StoreError::Locked`] once the timeout has passed.
+pub(crate) fn acquire(
+    backend: &dyn Backend,
+    path: &Path,
+    mode: LockMode,
+    timeout: Option<Duration>,
+) -> Result<Lock>
+{
+    let start = Instant::now();
+    let mut backoff = Duration::from_millis(1);
+
+    loop
+    {
+        if let Some(lock) = backend.try_lock(path, mode)?
+        {
+            return Ok(lock);
+        }
+
+        let mut sleep = backoff;
+
+        if let Some(timeout) = timeout
+        {
+            let remaining = timeout.saturating_sub(start.elapsed());
+
+            if remaining.is_zero()
+            {
+                return Err(StoreError::Locked {
+                    path: path.to_path_buf(),
+                });
+            }
+
+            sleep = cmp::min(sleep, remaining);
+        }
+
+        thread::sleep(sleep);
+        backoff = cmp::min(backoff * 2, MAX_BACKOFF);
+    }
+}
+
+impl<K, T, F> Store<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Lock the whole store, waiting until no one else holds a conflicting
+    /// lock.
+    ///
+    /// Store locks and entry locks are independent of each other. A store
+    /// returned by [`Store::scope`] shares the lock of the store it was taken
+    /// from.
+    pub fn lock(&self, mode: LockMode) -> Result<Lock>
+    {
+        self.lock_store(mode, None)
+    }
+
+    /// Lock the whole store, failing with [`StoreError::Locked`] if someone
+    /// else holds a conflicting lock.
+    pub fn try_lock(&self, mode: LockMode) -> Result<Lock>
+    {
+        self.lock_store(mode, Some(Duration::ZERO))
+    }
+
+    /// Lock the whole store, failing with [`StoreError::Locked`] if it is not
+    /// released within `timeout`.
+    pub fn lock_timeout(&self, mode: LockMode, timeout: Duration) -> Result<Lock>
+    {
+        self.lock_store(mode, Some(timeout))
+    }
+
+    /// Lock a single entry, waiting until no one else holds a conflicting
+    /// lock. The entry does not need to exist.
+    ///
+    /// The entry's lock file is kept after the entry is deleted, see the
+    /// [`lock`](self) module.
+    pub fn lock_entry<Q>(&self, key: Q, mode: LockMode) -> Result<Lock>
+    where
+        Q: Into<K>,
+    {
+        let path = self.entry_lock_path(&key.into())?;
+
+        acquire(&*self.options.backend, &path, mode, None)
+    }
+
+    /// Lock a single entry, failing with [`StoreError::Locked`] if someone
+    /// else holds a conflicting lock.
+    pub fn try_lock_entry<Q>(&self, key: Q, mode: LockMode) -> Result<Lock>
+    where
+        Q: Into<K>,
+    {
+        let path = self.entry_lock_path(&key.into())?;
+
+        acquire(&*self.options.backend, &path, mode, Some(Duration::ZERO))
+    }
+
+    /// Lock a single entry, failing with [`StoreError::Locked`] if it is not
+    /// released within `timeout`.
+    pub fn lock_entry_timeout<Q>(&self, key: Q, mode: LockMode, timeout: Duration) -> Result<Lock>
+    where
+        Q: Into<K>,
+    {
+        let path = self.entry_lock_path(&key.into())?;
+
+        acquire(&*self.options.backend, &path, mode, Some(timeout))
+    }
+
+    /// Lock the whole store, waiting up to `timeout`.
+    fn lock_store(&self, mode: LockMode, timeout: Option<Duration>) -> Result<Lock>
+    {
+        let Some(store_lock) = &self.options.store_lock
+        else
+        {
+            let path = self.path.join(FILE_NAME);
+
+            return acquire(&*self.options.backend, &path, mode, timeout);
+        };
+
+        let held = match mode
+        {
+            LockMode::Shared => store_lock.shared(timeout)?,
+            LockMode::Exclusive => store_lock.exclusive(timeout)?,
+        };
+
+        Ok(Lock::new(store_lock.path.clone(), mode, held))
+    }
+
+    /// Get the path to the lock file of an entry.
+    fn entry_lock_path(&self, key: &K) -> Result<PathBuf>
+    {
+        let path = self.path_for(key)?;
+        let relative = path.strip_prefix(&self.path).unwrap_or(&path);
+        let mut name = OsString::from(relative);
+
+        name.push(".lock");
+
+        Ok(self.path.join(DIR_NAME).join(name))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{MemoryBackend, Options};
+
+    /// Test that locks conflict as their mode says and are released on drop.
+    #[test]
+    fn test_lock() -> Result<()>
+    {
+        let options = Options::new()
+            .backend(MemoryBackend::new())
+            .lock_timeout(Duration::ZERO);
+        let mut store: Store<String, u32> = Store::with_options("test", options.clone())?;
+        let mut other: Store<String, u32> = Store::with_options("test", options.clone())?;
+
+        let shared = store.try_lock(LockMode::Shared)?;
+
+        assert_eq!(shared.mode(), LockMode::Shared);
+        assert!(other.try_lock(LockMode::Shared).is_ok());
+        assert!(matches!(
+            other.try_lock(LockMode::Exclusive),
+            Err(StoreError::Locked { .. })
+        ));
+
+        assert!(matches!(store.save("a", 1), Err(StoreError::Locked { .. })));
+        assert!(matches!(other.save("a", 1), Err(StoreError::Locked { .. })));
+
+        drop(shared);
+
+        let exclusive = other.lock(LockMode::Exclusive)?;
+
+        other.save("a", 1)?;
+        other.delete("a")?;
+
+        assert!(matches!(store.save("a", 1), Err(StoreError::Locked { .. })));
+
+        let start = Instant::now();
+
+        assert!(matches!(
+            store.lock_timeout(LockMode::Shared, Duration::from_millis(20)),
+            Err(StoreError::Locked { .. })
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let entry = store.try_lock_entry("a", LockMode::Exclusive)?;
+
+        assert!(other.try_lock_entry("b", LockMode::Exclusive).is_ok());
+        assert!(matches!(
+            other.lock_entry_timeout("a", LockMode::Shared, Duration::ZERO),
+            Err(StoreError::Locked { .. })
+        ));
+
+        assert!(matches!(
+            Store::<String, u32>::open_with("test", options.clone().read_only()),
+            Err(StoreError::Locked { .. })
+        ));
+
+        drop((exclusive, entry));
+
+        assert!(store.try_lock(LockMode::Exclusive).is_ok());
+        assert!(store.lock_entry("a", LockMode::Exclusive).is_ok());
+
+        let (mut reader, _) = Store::<String, u32>::open_with("test", options.read_only())?;
+
+        assert_eq!(reader.get("a"), None);
+        assert!(matches!(
+            reader.save("a", 1),
+            Err(StoreError::ReadOnly { .. })
+        ));
+        assert!(matches!(
+            reader.try_lock(LockMode::Exclusive),
+            Err(StoreError::ReadOnly { .. })
+        ));
+
+        other.save("a", 1)?;
+
+        let exclusive = other.try_lock(LockMode::Exclusive)?;
+
+        assert!(matches!(reader.scan(), Err(StoreError::Locked { .. })));
+
+        drop(exclusive);
+
+        let shared = reader.try_lock(LockMode::Shared)?;
+
+        assert!(matches!(other.save("a", 2), Err(StoreError::Locked { .. })));
+
+        drop(shared);
+
+        let (_, report) = reader.scan()?;
+
+        assert_eq!(report.loaded, vec![String::from("a")]);
+        assert_eq!(reader.get("a"), Some(&1));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use super::{
+    error::{Result, StoreError},
+    lock, Format, Json, Options, PrettyJson,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The name of the manifest file inside a store's directory.
+pub(crate) const FILE_NAME: &str = ".store.json";
+
+/// What a store was created with, recorded in a hidden file in its directory.
+///
+/// The manifest is written when a store is first created, and every later
+/// open fails with [`StoreError::Mismatch`] unless the format and tag match.
+/// The format is recorded by its [`Format::NAME`], and the tag is whatever was
+/// set with [`Options::tag`] to name the key and value types, so neither
+/// changes with the compiler version or when a type is moved. A store opened
+/// without a tag does not check it, and a store without a tag records the
+/// first one it is opened with. Opening a store with a newer schema version
+/// upgrades the manifest, opening it with an older one fails.
+///
+/// A read-only store never writes its manifest. A store without one, such as
+/// a store created before manifests existed, is opened without any checks,
+/// and a newer schema version or new tag is only recorded in memory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest
+{
+    /// The version of this crate that created the store.
+    pub crate_version: String,
+    /// The name of the format, see [`Format::NAME`].
+    pub format: String,
+    /// The name of the key and value types, see [`Options::tag`].
+    pub tag: Option<String>,
+    /// The version of the schema values are stored with.
+    pub schema_version: u32,
+    /// When the store was created, in seconds since the Unix epoch.
+    pub created: u64,
+}
+
+impl Manifest
+{
+    /// Create the manifest of a store with the given options.
+    pub(crate) fn new<F>(options: &Options<F>) -> Self
+    where
+        F: Format,
+    {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            format: F::NAME.to_string(),
+            tag: options.tag.clone(),
+            schema_version: options.schema_version,
+            created,
+        }
+    }
+
+    /// Load the manifest of the store at `dir`, creating it if the store has
+    /// none yet, and check that it matches the given options.
+    ///
+    /// A read-only store gets the expected manifest if it has none.
+    pub(crate) fn open<F>(dir: &Path, options: &Options<F>) -> Result<Self>
+    where
+        F: Format,
+    {
+        let path = dir.join(FILE_NAME);
+        let expected = Self::new(options);
+
+        let found = match Self::read(&path, options)?
+        {
+            Some(found) => found,
+            None if options.read_only => return Ok(expected),
+            None =>
+            {
+                let bytes = PrettyJson
+                    .serialize(&expected)
+                    .map_err(|error| StoreError::serialize(&path, error))?;
+                let _held = lock::write(options.store_lock.as_ref())?;
+
+                match options.backend.write_new(&path, &bytes, options.durability)
+                {
+                    Ok(()) => return Ok(expected),
+                    Err(StoreError::AlreadyExists { .. }) => Self::read(&path, options)?
+                        .ok_or(StoreError::NotFound { path: path.clone() })?,
+                    Err(error) => return Err(error),
+                }
+            }
+        };
+
+        compare(dir, &[("format", &expected.format, &found.format)])?;
+
+        if let (Some(expected), Some(found)) = (&expected.tag, &found.tag)
+        {
+            compare(dir, &[("tag", expected, found)])?;
+        }
+
+        if found.schema_version > expected.schema_version
+        {
+            return Err(StoreError::Mismatch {
+                path: dir.to_path_buf(),
+                what: "schema version",
+                expected: expected.schema_version.to_string(),
+                found: found.schema_version.to_string(),
+            });
+        }
+
+        let upgraded = Self {
+            schema_version: expected.schema_version,
+            tag: found.tag.clone().or(expected.tag),
+            ..found.clone()
+        };
+
+        if upgraded != found && !options.read_only
+        {
+            upgraded.write(&path, options)?;
+        }
+
+        Ok(upgraded)
+    }
+
+    /// Write a manifest, replacing any existing one.
+    fn write<F>(&self, path: &Path, options: &Options<F>) -> Result<()>
+    where
+        F: Format,
+    {
+        let bytes = PrettyJson
+            .serialize(self)
+            .map_err(|error| StoreError::serialize(path, error))?;
+        let _held = lock::write(options.store_lock.as_ref())?;
+
+        options
+            .backend
+            .write_atomic(path, &bytes, options.durability)
+    }
+
+    /// Read a manifest, returning `None` if it does not exist.
+    pub(crate) fn read<F>(path: &Path, options: &Options<F>) -> Result<Option<Self>>
+    {
+        let bytes = match options.backend.read(path)
+        {
+            Ok(bytes) => bytes,
+            Err(StoreError::NotFound { .. }) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        Json.deserialize(&bytes)
+            .map(Some)
+            .map_err(|error| StoreError::corrupt(path, error))
+    }
+}
+
+/// Check that every field something is opened with matches what it was
+/// created with, as `(what, expected, found)`.
+///
+/// Fails with [`StoreError::Mismatch`] on the first field that differs.
+pub(crate) fn compare(path: &Path, fields: &[(&'static str, &String, &String)]) -> Result<()>
+{
+    for &(what, expected, found) in fields
+    {
+        if expected != found
+        {
+            return Err(StoreError::Mismatch {
+                path: path.to_path_buf(),
+                what,
+                expected: expected.clone(),
+                found: found.clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,358 @@
+//! Versioned envelopes and migration of stored values between schema versions.
+//!
+//! A store with a schema version above zero wraps every value in an envelope
+//! recording the version it was written with:
+//!
+//! ```json
+//! {"$version": 2, "$data": {"name": "Milan"}}
+//! ```
+//!
+//! Only an object with exactly these two keys is an envelope, files holding
+//! anything else are treated as version zero. When a value older than the
+//! store's schema version is loaded, the registered [`Migrations`] are applied
+//! one version at a time before it is deserialized.
+
+use super::{
+    error::{Result, StoreError},
+    Format, FormatError, Options,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug, Formatter},
+    path::Path,
+    sync::Arc,
+};
+
+/// A migration step from one schema version to the next.
+type Step = Arc<dyn Fn(Value) -> Result<Value, FormatError> + Send + Sync>;
+
+/// A registry of migration steps between schema versions.
+///
+/// Every step migrates a value from the version it is registered for to the
+/// next one, so migrating from version 1 to 3 runs the steps registered for
+/// versions 1 and 2 in order.
+///
+/// # Example
+///
+/// ```no_run
+/// use serde::{Deserialize, Serialize};
+/// use serde_json::{json, Value};
+/// use store::{Migrations, Options, Store};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct UserV1
+/// {
+///     name: String,
+/// }
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct User
+/// {
+///     first_name: String,
+///     last_name: String,
+/// }
+///
+/// # fn main() -> store::Result<()> {
+/// let migrations = Migrations::new()
+///     .step(0, |value: Value| json!({ "name": value }))
+///     .upcast(1, |user: UserV1| User {
+///         first_name: user.name,
+///         last_name: String::new(),
+///     });
+///
+/// let options = Options::new().schema_version(2).migrations(migrations);
+/// let (store, _) = Store::<String, User>::open_with("users", options)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Migrations
+{
+    /// The migration steps, by the version they migrate from.
+    steps: BTreeMap<u32, Step>,
+}
+
+impl Migrations
+{
+    /// Create an empty registry.
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Register a step migrating the raw value of version `from` to the next
+    /// version.
+    pub fn step<M>(self, from: u32, step: M) -> Self
+    where
+        M: Fn(Value) -> Value + Send + Sync + 'static,
+    {
+        self.try_step(from, move |value| Ok(step(value)))
+    }
+
+    /// Register a fallible step migrating the raw value of version `from` to
+    /// the next version.
+    pub fn try_step<M>(mut self, from: u32, step: M) -> Self
+    where
+        M: Fn(Value) -> Result<Value, FormatError> + Send + Sync + 'static,
+    {
+        self.steps.insert(from, Arc::new(step));
+        self
+    }
+
+    /// Register a step converting the typed value of version `from` into the
+    /// type of the next version.
+    pub fn upcast<A, B, M>(self, from: u32, upcast: M) -> Self
+    where
+        A: DeserializeOwned,
+        B: Serialize,
+        M: Fn(A) -> B + Send + Sync + 'static,
+    {
+        self.try_step(from, move |value| {
+            let value = upcast(serde_json::from_value(value)?);
+
+            Ok(serde_json::to_value(value)?)
+        })
+    }
+
+    /// Check whether no steps are registered.
+    pub fn is_empty(&self) -> bool
+    {
+        self.steps.is_empty()
+    }
+
+    /// Migrate a value from version `from` to version `to`.
+    ///
+    /// Fails with the version whose step failed or is missing.
+    fn migrate(&self, mut value: Value, from: u32, to: u32) -> Result<Value, (u32, FormatError)>
+    {
+        for version in from..to
+        {
+            let step = self.steps.get(&version).ok_or_else(|| {
+                let error = format!("no migration from schema version {}", version);
+                (version, FormatError::new(error))
+            })?;
+
+            value = step(value).map_err(|error| (version, error))?;
+        }
+
+        Ok(value)
+    }
+}
+
+impl Debug for Migrations
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        f.debug_struct("Migrations")
+            .field("versions", &self.steps.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// The key of an envelope holding the schema version.
+const VERSION: &str = "$version";
+
+/// The key of an envelope holding the value.
+const DATA: &str = "$data";
+
+/// A value wrapped with the schema version it was written with.
+#[derive(Serialize)]
+struct Envelope<V>
+{
+    /// The schema version.
+    #[serde(rename = "$version")]
+    version: u32,
+    /// The value.
+    #[serde(rename = "$data")]
+    data: V,
+}
+
+/// Serialize a value, wrapping it in an envelope if `version` is above zero.
+pub(crate) fn encode<T, F>(format: &F, version: u32, value: &T) -> Result<Vec<u8>, FormatError>
+where
+    T: Serialize,
+    F: Format,
+{
+    if version == 0
+    {
+        return format.serialize(value);
+    }
+
+    format.serialize(&Envelope {
+        version,
+        data: value,
+    })
+}
+
+/// Deserialize a value read from `path`, migrating it to the schema version
+/// of the options.
+///
+/// Returns whether the value was migrated, in which case it is still stored
+/// in its old version on disk.
+pub(crate) fn decode<T, F>(path: &Path, bytes: &[u8], options: &Options<F>) -> Result<(T, bool)>
+where
+    T: DeserializeOwned,
+    F: Format,
+{
+    let target = options.schema_version;
+    let format = &options.format;
+
+    if target == 0
+    {
+        let value = format
+            .deserialize(bytes)
+            .map_err(|error| StoreError::corrupt(path, error))?;
+
+        return Ok((value, false));
+    }
+
+    let value = format
+        .deserialize(bytes)
+        .map_err(|error| StoreError::corrupt(path, error))?;
+    let (version, data) = unwrap(value);
+
+    if version > target
+    {
+        let error = format!("newer than schema version {}", target);
+
+        return Err(StoreError::Migration {
+            path: path.to_path_buf(),
+            version,
+            source: FormatError::new(error),
+        });
+    }
+
+    let data = options
+        .migrations
+        .migrate(data, version, target)
+        .map_err(|(version, source)| StoreError::Migration {
+            path: path.to_path_buf(),
+            version,
+            source,
+        })?;
+
+    let value = serde_json::from_value(data)
+        .map_err(|error| StoreError::corrupt(path, FormatError::new(error)))?;
+
+    Ok((value, version < target))
+}
+
+/// Check whether a raw value is an envelope holding a sequence.
+pub(crate) fn is_sequence_envelope(value: &Value) -> bool
+{
+    envelope_version(value).is_some() && value[DATA].is_array()
+}
+
+/// Get the schema version of an envelope, an object with exactly the
+/// `$version` and `$data` keys, or `None` if the value is not one.
+fn envelope_version(value: &Value) -> Option<u32>
+{
+    let Value::Object(map) = value
+    else
+    {
+        return None;
+    };
+
+    if map.len() != 2 || !map.contains_key(DATA)
+    {
+        return None;
+    }
+
+    map.get(VERSION)?.as_u64()?.try_into().ok()
+}
+
+/// Split a raw value into the schema version it was written with and its
+/// data. Anything but an envelope is data of version zero.
+fn unwrap(value: Value) -> (u32, Value)
+{
+    match (envelope_version(&value), value)
+    {
+        (Some(version), Value::Object(mut map)) =>
+        {
+            (version, map.remove(DATA).unwrap_or(Value::Null))
+        }
+        (_, value) => (0, value),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{Json, MemoryBackend};
+    use serde_json::json;
+
+    /// Test that values are migrated through every step up to the target.
+    #[test]
+    fn test_migrate() -> Result<()>
+    {
+        let migrations = Migrations::new()
+            .step(0, |value| json!({ "count": value }))
+            .upcast(1, |value: Value| json!([value["count"]]));
+
+        let options = Options::new()
+            .backend(MemoryBackend::new())
+            .schema_version(2)
+            .migrations(migrations);
+        let path = Path::new("test.json");
+
+        let (value, migrated) = decode::<Vec<u32>, _>(path, b"7", &options)?;
+
+        assert_eq!(value, vec![7]);
+        assert!(migrated);
+
+        let bytes = encode(&Json, 2, &vec![7u32]).unwrap();
+
+        assert_eq!(bytes, br#"{"$version":2,"$data":[7]}"#);
+        assert_eq!(
+            decode::<Vec<u32>, _>(path, &bytes, &options)?,
+            (vec![7], false)
+        );
+
+        let newer = encode(&Json, 3, &vec![7u32]).unwrap();
+
+        assert!(matches!(
+            decode::<Vec<u32>, _>(path, &newer, &options),
+            Err(StoreError::Migration { version: 3, .. })
+        ));
+        assert!(matches!(
+            decode::<Vec<u32>, _>(path, b"7", &options.clone().migrations(Migrations::new())),
+            Err(StoreError::Migration { version: 0, .. })
+        ));
+
+        Ok(())
+    }
+
+    /// Test that values without an envelope are read as version zero,
+    /// whatever their shape.
+    #[test]
+    fn test_legacy_shapes() -> Result<()>
+    {
+        let options = Options::new()
+            .backend(MemoryBackend::new())
+            .schema_version(1)
+            .migrations(Migrations::new().step(0, |value| value));
+        let path = Path::new("test.json");
+
+        assert_eq!(
+            decode::<(u32, String), _>(path, br#"[1,"x"]"#, &options)?,
+            ((1, String::from("x")), true)
+        );
+        assert_eq!(
+            decode::<Value, _>(path, br#"[1,"x"]"#, &options)?,
+            (json!([1, "x"]), true)
+        );
+        assert_eq!(
+            decode::<Value, _>(path, br#"{"$version":0,"$data":1,"other":2}"#, &options)?,
+            (json!({ "$version": 0, "$data": 1, "other": 2 }), true)
+        );
+        assert_eq!(
+            decode::<Value, _>(path, br#"{"$version":1,"$data":[1,"x"]}"#, &options)?,
+            (json!([1, "x"]), false)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use super::{
+    backend::ReadOnlyBackend, lock::StoreLock, Backend, Durability, Format, FsBackend, Json,
+    Migrations,
+};
+use std::{sync::Arc, time::Duration};
+
+/// Options used to open a store or stored value.
+///
+/// # Example
+///
+/// ```no_run
+/// use store::{Durability, Options, PrettyJson, Store};
+///
+/// # fn main() -> store::Result<()> {
+/// let options = Options::new().format(PrettyJson).durability(Durability::Flush);
+/// let mut store: Store<String, String, _> = Store::with_options("data", options)?;
+///
+/// store.save("test", String::from("Hello, world!"))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Options<F = Json>
+{
+    /// The format values are stored in.
+    pub(crate) format: F,
+    /// How durable saves are.
+    pub(crate) durability: Durability,
+    /// The storage files are read from and written to.
+    pub(crate) backend: Arc<dyn Backend>,
+    /// The version of the schema values are stored with.
+    pub(crate) schema_version: u32,
+    /// The name of the key and value types recorded in the manifest of a
+    /// store.
+    pub(crate) tag: Option<String>,
+    /// The steps migrating values from older schema versions.
+    pub(crate) migrations: Migrations,
+    /// Whether migrated values are written back as soon as they are loaded.
+    pub(crate) migrate_eagerly: bool,
+    /// Whether stores are opened read-only.
+    pub(crate) read_only: bool,
+    /// How long writes, and reads of read-only stores, wait for the store's
+    /// lock, or `None` to wait forever.
+    pub(crate) lock_timeout: Option<Duration>,
+    /// The lock of the store values belong to, set by the store itself.
+    pub(crate) store_lock: Option<Arc<StoreLock>>,
+}
+
+impl Options
+{
+    /// Create the default options, storing compact JSON on the filesystem
+    /// with full durability.
+    pub fn new() -> Self
+    {
+        Self {
+            format: Json,
+            durability: Durability::default(),
+            backend: Arc::new(FsBackend),
+            schema_version: 0,
+            tag: None,
+            migrations: Migrations::new(),
+            migrate_eagerly: false,
+            read_only: false,
+            lock_timeout: None,
+            store_lock: None,
+        }
+    }
+}
+
+impl Default for Options
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl<F> Options<F>
+where
+    F: Format,
+{
+    /// Set the format values are stored in.
+    pub fn format<G>(self, format: G) -> Options<G>
+    where
+        G: Format,
+    {
+        Options {
+            format,
+            durability: self.durability,
+            backend: self.backend,
+            schema_version: self.schema_version,
+            tag: self.tag,
+            migrations: self.migrations,
+            migrate_eagerly: self.migrate_eagerly,
+            read_only: self.read_only,
+            lock_timeout: self.lock_timeout,
+            store_lock: self.store_lock,
+        }
+    }
+
+    /// Set how durable saves are.
+    pub fn durability(mut self, durability: Durability) -> Self
+    {
+        self.durability = durability;
+        self
+    }
+
+    /// Set the version of the schema values are stored with, which is
+    /// recorded in the manifest of a store. Defaults to zero.
+    ///
+    /// Above zero every value is written in an envelope recording its
+    /// version, see [`migration`](crate::migration).
+    pub fn schema_version(mut self, version: u32) -> Self
+    {
+        self.schema_version = version;
+        self
+    }
+
+    /// Set a name for the key and value types, which is recorded in the
+    /// manifest of a store. Opening the store with another tag fails with
+    /// [`StoreError::Mismatch`], see [`Manifest`].
+    ///
+    /// Tags are chosen by the program rather than derived from the types, so
+    /// they stay the same when a type is renamed or moved, and should be
+    /// changed along with the types' serialized shape.
+    ///
+    /// [`StoreError::Mismatch`]: crate::StoreError::Mismatch
+    /// [`Manifest`]: crate::Manifest
+    pub fn tag<S>(mut self, tag: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Set the steps migrating values from older schema versions.
+    pub fn migrations(mut self, migrations: Migrations) -> Self
+    {
+        self.migrations = migrations;
+        self
+    }
+
+    /// Set whether migrated values are written back as soon as they are
+    /// loaded, rather than on their next save. Defaults to `false`.
+    pub fn migrate_eagerly(mut self, eagerly: bool) -> Self
+    {
+        self.migrate_eagerly = eagerly;
+        self
+    }
+
+    /// Set how long writes, and reads of read-only stores, wait for the
+    /// store's lock before failing with [`StoreError::Locked`]. They wait
+    /// forever by default.
+    ///
+    /// Every write to a store holds its lock exclusively, and every read of a
+    /// read-only store holds it shared, see [`lock`](crate::lock).
+    ///
+    /// [`StoreError::Locked`]: crate::StoreError::Locked
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self
+    {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the storage files are read from and written to.
+    pub fn backend<B>(mut self, backend: B) -> Self
+    where
+        B: Backend + 'static,
+    {
+        self.backend = Arc::new(backend);
+
+        if self.read_only
+        {
+            self.backend = Arc::new(ReadOnlyBackend::new(self.backend));
+        }
+
+        self
+    }
+
+    /// Open stores read-only.
+    ///
+    /// A read-only store holds a shared lock on its directory while it reads
+    /// from disk, such as while opening or scanning it, so those reads wait
+    /// while anyone holds the lock exclusively, see
+    /// [`Options::lock_timeout`]. Writers are not kept out in between. Every
+    /// write, including taking an exclusive lock, fails with
+    /// [`StoreError::ReadOnly`].
+    ///
+    /// [`StoreError::ReadOnly`]: crate::StoreError::ReadOnly
+    pub fn read_only(mut self) -> Self
+    {
+        if !self.read_only
+        {
+            self.read_only = true;
+            self.backend = Arc::new(ReadOnlyBackend::new(self.backend));
+        }
+
+        self
+    }
+}
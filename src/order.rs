@@ -0,0 +1,17 @@
+/// The order in which the entries of a store are visited.
+///
+/// Iterating a store always visits entries by key. The other orders are
+/// selected per call through [`Store::sorted_by`](super::Store::sorted_by).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Order
+{
+    /// Order entries by key.
+    #[default]
+    Key,
+    /// Order entries by when they were added to the store. Entries loaded from
+    /// disk are added in key order.
+    Insertion,
+    /// Order entries by when their file was last modified, oldest first.
+    /// Entries modified at the same time are ordered by key.
+    Modified,
+}
@@ -0,0 +1,77 @@
+use super::StoreError;
+use std::path::PathBuf;
+
+/// A file that could not be loaded while scanning a store.
+#[derive(Debug)]
+pub struct LoadFailure
+{
+    /// The path to the file.
+    pub path: PathBuf,
+    /// The reason the file could not be loaded.
+    pub error: StoreError,
+}
+
+/// Why a path in a store's directory was not loaded as an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SkipReason
+{
+    /// The path is a directory.
+    Directory,
+    /// The file is a temporary file left behind by an interrupted write.
+    Temporary,
+    /// The file name starts with a dot.
+    Hidden,
+    /// The file does not have the extension of the store's format.
+    Extension,
+    /// The file name is not valid UTF-8 or does not decode into a key.
+    InvalidName,
+}
+
+/// A path in a store's directory that was not loaded as an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped
+{
+    /// The path that was skipped.
+    pub path: PathBuf,
+    /// Why the path was skipped.
+    pub reason: SkipReason,
+}
+
+/// The outcome of scanning a store's directory.
+#[derive(Debug)]
+pub struct ScanReport<K = String>
+{
+    /// The keys of the entries found on disk.
+    pub entries: Vec<K>,
+    /// The keys of the entries that were newly loaded from disk.
+    pub loaded: Vec<K>,
+    /// The keys of entries in the store whose file no longer exists.
+    pub missing: Vec<K>,
+    /// The paths that are not entries.
+    pub skipped: Vec<Skipped>,
+    /// The entries that could not be loaded.
+    pub failed: Vec<LoadFailure>,
+}
+
+impl<K> ScanReport<K>
+{
+    /// Check whether every entry was loaded and nothing was missing.
+    pub fn is_clean(&self) -> bool
+    {
+        self.missing.is_empty() && self.failed.is_empty()
+    }
+}
+
+impl<K> Default for ScanReport<K>
+{
+    fn default() -> Self
+    {
+        Self {
+            entries: Vec::new(),
+            loaded: Vec::new(),
+            missing: Vec::new(),
+            skipped: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+}
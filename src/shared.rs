@@ -0,0 +1,440 @@
+use super::{
+    error::{Result, StoreError},
+    key::StoreKey,
+    lock,
+    scan::ScanReport,
+    snapshot::Snapshot,
+    Format, Json, Options, Store, Stored,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    mem,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+/// The lock around a single entry, holding `None` while the entry does not
+/// exist.
+type Slot<T, F> = Arc<RwLock<Option<Stored<T, F>>>>;
+
+/// A store that can be shared between threads, with a lock per entry.
+///
+/// Clones share the same entries. Reading or writing an entry only locks that
+/// entry, so threads working on different keys never wait for each other's
+/// reads or writes. The set of keys itself is only locked briefly to look up,
+/// add or remove an entry.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::thread;
+/// use store::SharedStore;
+///
+/// # fn main() -> store::Result<()> {
+/// let (store, _) = SharedStore::<String, u64>::open("counters")?;
+///
+/// let workers: Vec<_> = (0..4)
+///     .map(|worker| {
+///         let store = store.clone();
+///
+///         thread::spawn(move || store.save(format!("worker-{}", worker), 0))
+///     })
+///     .collect();
+///
+/// for worker in workers
+/// {
+///     worker.join().expect("worker panicked")?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct SharedStore<K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The state shared by every clone.
+    inner: Arc<Inner<K, T, F>>,
+}
+
+/// The state shared by every clone of a shared store.
+struct Inner<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The store the entries were taken from, used for its path and options.
+    store: Store<K, T, F>,
+    /// The entries, each behind a lock of its own.
+    entries: RwLock<BTreeMap<K, Slot<T, F>>>,
+    /// The insertion order given to the last entry added to the store.
+    sequence: AtomicU64,
+}
+
+impl<K, T> SharedStore<K, T>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+{
+    /// Open a shared store, loading every entry that is already on disk.
+    ///
+    /// See [`Store::open`].
+    pub fn open<P>(path: P) -> Result<(Self, ScanReport<K>)>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_with(path, Options::new())
+    }
+}
+
+impl<K, T, F> SharedStore<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Open a shared store using the given options.
+    ///
+    /// See [`Store::open_with`].
+    pub fn open_with<P>(path: P, options: Options<F>) -> Result<(Self, ScanReport<K>)>
+    where
+        P: AsRef<Path>,
+    {
+        let (store, report) = Store::open_with(path, options)?;
+
+        Ok((Self::from(store), report))
+    }
+
+    /// Get a clone of data in the store.
+    pub fn get<Q>(&self, key: &Q) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        T: Clone,
+    {
+        self.with(key, T::clone)
+    }
+
+    /// Call `f` with a reference to data in the store, returning its result.
+    ///
+    /// The entry cannot be written while `f` runs.
+    pub fn with<Q, R, M>(&self, key: &Q, f: M) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        M: FnOnce(&T) -> R,
+    {
+        let slot = self.slot(key)?;
+        let stored = lock::recover(slot.read());
+
+        stored.as_ref().map(|stored| f(stored.value()))
+    }
+
+    /// Check whether the store has an entry with the given key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.with(key, |_| ()).is_some()
+    }
+
+    /// Get the keys of the store, in order.
+    ///
+    /// Every entry is locked briefly to check that it exists, so this waits
+    /// for writes to entries that are in progress.
+    pub fn keys(&self) -> Vec<K>
+    {
+        lock::recover(self.inner.entries.read())
+            .iter()
+            .filter(|(_, slot)| lock::recover(slot.read()).is_some())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Get the number of entries in the store.
+    ///
+    /// Waits for writes that are in progress, like [`SharedStore::keys`].
+    pub fn len(&self) -> usize
+    {
+        lock::recover(self.inner.entries.read())
+            .values()
+            .filter(|slot| lock::recover(slot.read()).is_some())
+            .count()
+    }
+
+    /// Check whether the store has no entries.
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    /// Take a snapshot of every entry in the store, see [`Snapshot`].
+    ///
+    /// Every entry is locked for reading while the snapshot is taken, so it
+    /// waits for writes that are in progress and holds off new ones until it
+    /// is done, which makes it show every entry as it was at a single point
+    /// in time. Unlike [`Store::snapshot`] every entry is looked at, so this
+    /// takes time linear in the number of entries.
+    pub fn snapshot(&self) -> Snapshot<K, T>
+    where
+        T: Clone,
+    {
+        let entries = lock::recover(self.inner.entries.read());
+        let slots: Vec<_> = entries
+            .iter()
+            .map(|(key, slot)| (key, lock::recover(slot.read())))
+            .collect();
+
+        self.inner.store.snapshot_of(
+            slots
+                .iter()
+                .filter_map(|(key, stored)| Some((*key, stored.as_ref()?))),
+        )
+    }
+
+    /// Save data to the store, replacing any existing value.
+    ///
+    /// Only the entry being saved is locked while the file is written.
+    pub fn save<Q>(&self, key: Q, value: T) -> Result<()>
+    where
+        Q: Into<K>,
+    {
+        let key = key.into();
+        let path = self.inner.store.path_for(&key)?;
+        let slot = self.slot_or_insert(&key);
+        let mut stored = lock::recover(slot.write());
+
+        let result = match stored.as_mut()
+        {
+            Some(stored) => stored.store(value),
+            None => self.inner.store.create_parent(&path).and_then(|()| {
+                let mut created = Stored::with_value(path, value, &self.inner.store.options);
+
+                created.sequence = self.inner.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+                created.save()?;
+                *stored = Some(created);
+
+                Ok(())
+            }),
+        };
+
+        drop(stored);
+        drop(slot);
+
+        if result.is_err()
+        {
+            self.remove_if_unused(&key);
+        }
+
+        result
+    }
+
+    /// Modify existing data in the store in place and save it, returning the
+    /// result of `f`.
+    ///
+    /// Fails with [`StoreError::NotFound`] if the entry does not exist. If
+    /// saving fails the modification is kept in memory, see
+    /// [`Stored::modify`].
+    pub fn modify<Q, M, R>(&self, key: &Q, f: M) -> Result<R>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = K> + ?Sized,
+        M: FnOnce(&mut T) -> R,
+    {
+        let path = self.inner.store.path_for(&key.to_owned())?;
+        let slot = self.slot(key);
+        let mut stored = slot.as_ref().map(|slot| lock::recover(slot.write()));
+
+        match stored.as_mut().and_then(|stored| stored.as_mut())
+        {
+            Some(stored) => stored.modify(f),
+            None => Err(StoreError::NotFound { path }),
+        }
+    }
+
+    /// Delete data from the store.
+    ///
+    /// Empty directories of nested keys are left in place, since another
+    /// thread may be saving an entry into them.
+    pub fn delete<Q>(&self, key: &Q) -> Result<()>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = K> + ?Sized,
+    {
+        let path = self.inner.store.path_for(&key.to_owned())?;
+        let slot = self
+            .slot(key)
+            .ok_or_else(|| StoreError::NotFound { path: path.clone() })?;
+        let mut stored = lock::recover(slot.write());
+
+        stored
+            .as_ref()
+            .ok_or(StoreError::NotFound { path })?
+            .delete()?;
+        *stored = None;
+
+        drop(stored);
+        drop(slot);
+
+        self.remove_if_unused(key);
+
+        Ok(())
+    }
+
+    /// Get the lock of an entry, if the store has one for it.
+    fn slot<Q>(&self, key: &Q) -> Option<Slot<T, F>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        lock::recover(self.inner.entries.read()).get(key).cloned()
+    }
+
+    /// Get the lock of an entry, adding an empty one if the store has none.
+    fn slot_or_insert(&self, key: &K) -> Slot<T, F>
+    {
+        if let Some(slot) = self.slot(key)
+        {
+            return slot;
+        }
+
+        lock::recover(self.inner.entries.write())
+            .entry(key.clone())
+            .or_default()
+            .clone()
+    }
+
+    /// Remove the lock of an entry that does not exist, unless another
+    /// thread is still using it.
+    fn remove_if_unused<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut entries = lock::recover(self.inner.entries.write());
+
+        // Locks are only handed out while holding the map, so a lock no one
+        // else has a reference to cannot be taken by anyone either.
+        let unused = entries.get(key).is_some_and(|slot| {
+            Arc::strong_count(slot) == 1 && lock::recover(slot.read()).is_none()
+        });
+
+        if unused
+        {
+            entries.remove(key);
+        }
+    }
+}
+
+impl<K, T, F> Clone for SharedStore<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    fn clone(&self) -> Self
+    {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, T, F> From<Store<K, T, F>> for SharedStore<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    fn from(mut store: Store<K, T, F>) -> Self
+    {
+        let entries = mem::take(&mut store.data)
+            .into_iter()
+            .map(|(key, stored)| (key, Arc::new(RwLock::new(Some(stored)))))
+            .collect();
+
+        Self {
+            inner: Arc::new(Inner {
+                sequence: AtomicU64::new(store.sequence),
+                store,
+                entries: RwLock::new(entries),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::MemoryBackend;
+    use std::thread;
+
+    /// Assert that a type can be shared between threads.
+    fn assert_send_sync<S: Send + Sync>() {}
+
+    /// Test that threads can read and write entries of a shared store at once.
+    #[test]
+    fn test_shared_store() -> Result<()>
+    {
+        assert_send_sync::<SharedStore<String, u32>>();
+
+        let options = Options::new().backend(MemoryBackend::new());
+        let (store, _) = SharedStore::<String, u32>::open_with("test", options.clone())?;
+
+        store.save("total", 0)?;
+
+        let workers: Vec<_> = (0..8)
+            .map(|worker| {
+                let store = store.clone();
+
+                thread::spawn(move || -> Result<()> {
+                    for _ in 0..10
+                    {
+                        store.modify("total", |total| *total += 1)?;
+                    }
+
+                    store.save(format!("worker-{}", worker), worker)?;
+                    store.delete("missing").unwrap_err();
+
+                    Ok(())
+                })
+            })
+            .collect();
+
+        for worker in workers
+        {
+            worker.join().expect("worker panicked")?;
+        }
+
+        let snapshot = store.snapshot();
+
+        assert_eq!(store.get("total"), Some(80));
+        assert_eq!(store.len(), 9);
+        assert_eq!(store.with("worker-3", |value| value * 2), Some(6));
+
+        store.delete("worker-3")?;
+
+        assert!(!store.contains_key("worker-3"));
+        assert_eq!(snapshot.get("worker-3"), Some(&3));
+        assert_eq!(snapshot.len(), 9);
+        assert!(matches!(
+            store.modify("worker-3", |_| ()),
+            Err(StoreError::NotFound { .. })
+        ));
+
+        let (reopened, _) = Store::<String, u32>::open_with("test", options)?;
+
+        assert_eq!(reopened.keys().cloned().collect::<Vec<_>>(), store.keys());
+
+        Ok(())
+    }
+}
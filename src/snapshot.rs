@@ -0,0 +1,510 @@
+use super::{key::StoreKey, lock, Format, Store, Stored, Version};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Borrow,
+    collections::{btree_map, BTreeMap, BTreeSet},
+    iter::FusedIterator,
+    sync::Arc,
+};
+
+/// An immutable view of every entry of a store at a point in time.
+///
+/// Returned by [`Store::snapshot`] and
+/// [`SharedStore::snapshot`](super::SharedStore::snapshot). A snapshot owns
+/// its entries, so it keeps working while the store goes on saving, and
+/// reading it takes no locks. Cloning a snapshot is cheap.
+///
+/// A store keeps the last snapshot taken of it along with the keys changed
+/// since, so taking a snapshot of an unchanged store is as cheap as cloning
+/// one. Otherwise only the changed values are cloned, and the index of keys is
+/// copied once if an earlier snapshot is still in use. Values that did not
+/// change, as told by their [`Version`], are shared rather than cloned. A
+/// value changed in memory without being saved, such as after a failed save,
+/// is always cloned again.
+///
+/// # Example
+///
+/// ```no_run
+/// use store::Store;
+///
+/// # fn main() -> store::Result<()> {
+/// let (mut store, _) = Store::<String, u64>::open("accounts")?;
+/// let snapshot = store.snapshot();
+///
+/// store.save("alice", 0)?;
+///
+/// let total: u64 = snapshot.iter().map(|(_, balance)| balance).sum();
+/// # Ok(())
+/// # }
+/// ```
+pub struct Snapshot<K, T>
+{
+    /// The entries, ordered by key.
+    entries: Arc<BTreeMap<K, Frozen<T>>>,
+}
+
+/// A value in a snapshot.
+struct Frozen<T>
+{
+    /// The version of the value's file when it was taken.
+    version: Option<Version>,
+    /// The value, shared with later snapshots while it does not change.
+    value: Arc<T>,
+}
+
+/// The last snapshot taken of a store, along with the keys changed since.
+pub(crate) struct Index<K, T>
+{
+    /// The last snapshot taken.
+    snapshot: Snapshot<K, T>,
+    /// The keys whose entries may have changed since the last snapshot.
+    changed: BTreeSet<K>,
+}
+
+impl<T> Frozen<T>
+{
+    /// Freeze the current value of an entry.
+    fn of<F>(stored: &Stored<T, F>) -> Self
+    where
+        for<'de> T: Serialize + Deserialize<'de> + Clone,
+        F: Format,
+    {
+        Self {
+            version: stored.version(),
+            value: Arc::new(stored.value().clone()),
+        }
+    }
+
+    /// Check whether the value is still the current value of an entry.
+    fn is_current<F>(&self, stored: &Stored<T, F>) -> bool
+    where
+        for<'de> T: Serialize + Deserialize<'de>,
+        F: Format,
+    {
+        !stored.modified && self.version.is_some() && self.version == stored.version()
+    }
+}
+
+impl<T> Clone for Frozen<T>
+{
+    fn clone(&self) -> Self
+    {
+        Self {
+            version: self.version,
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<K, T> Snapshot<K, T>
+where
+    K: Ord,
+{
+    /// Get data from the snapshot.
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.get(key).map(|frozen| &*frozen.value)
+    }
+
+    /// Get shared ownership of data in the snapshot, which outlives the
+    /// snapshot itself.
+    pub fn get_shared<Q>(&self, key: &Q) -> Option<Arc<T>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.get(key).map(|frozen| frozen.value.clone())
+    }
+
+    /// Get data from the snapshot along with the version of its file, see
+    /// [`Store::get_versioned`].
+    pub fn get_versioned<Q>(&self, key: &Q) -> Option<(&T, Version)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let frozen = self.entries.get(key)?;
+
+        Some((&*frozen.value, frozen.version?))
+    }
+
+    /// Check whether the snapshot has an entry with the given key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.contains_key(key)
+    }
+
+    /// Get all the values in the snapshot, ordered by key.
+    pub fn all(&self) -> Vec<&T>
+    {
+        self.iter().map(|(_, value)| value).collect()
+    }
+
+    /// Iterate over the keys and values of the snapshot, ordered by key.
+    pub fn iter(&self) -> SnapshotIter<'_, K, T>
+    {
+        SnapshotIter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    /// Get the number of entries in the snapshot.
+    pub fn len(&self) -> usize
+    {
+        self.entries.len()
+    }
+
+    /// Check whether the snapshot has no entries.
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    /// Take a snapshot of `entries`, sharing every value whose version did
+    /// not change since `previous` was taken and that was not modified in
+    /// memory since its file was last read or written.
+    ///
+    /// Returns `previous` itself if nothing changed.
+    pub(crate) fn update<'a, I, F>(previous: &Self, entries: I) -> Self
+    where
+        K: Clone + 'a,
+        for<'de> T: Serialize + Deserialize<'de> + Clone + 'a,
+        F: Format + 'a,
+        I: IntoIterator<Item = (&'a K, &'a Stored<T, F>)>,
+    {
+        let mut changed = false;
+
+        let entries: BTreeMap<_, _> = entries
+            .into_iter()
+            .map(|(key, stored)| {
+                let unchanged = previous
+                    .entries
+                    .get(key)
+                    .filter(|frozen| frozen.is_current(stored));
+
+                let frozen = match unchanged
+                {
+                    Some(frozen) => frozen.clone(),
+                    None =>
+                    {
+                        changed = true;
+                        Frozen::of(stored)
+                    }
+                };
+
+                (key.clone(), frozen)
+            })
+            .collect();
+
+        if !changed && entries.len() == previous.entries.len()
+        {
+            return previous.clone();
+        }
+
+        Self {
+            entries: Arc::new(entries),
+        }
+    }
+}
+
+impl<K, T> Index<K, T>
+where
+    K: Ord + Clone,
+{
+    /// Record that the entry with the given key may have changed.
+    fn touch(&mut self, key: &K)
+    {
+        if !self.changed.contains(key)
+        {
+            self.changed.insert(key.clone());
+        }
+    }
+
+    /// Bring the last snapshot up to date with `data` and return it.
+    ///
+    /// Only the entries changed since the last snapshot are looked at.
+    fn refresh<F>(&mut self, data: &BTreeMap<K, Stored<T, F>>) -> Snapshot<K, T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + Clone,
+        F: Format,
+    {
+        if !self.changed.is_empty()
+        {
+            let entries = Arc::make_mut(&mut self.snapshot.entries);
+
+            // The changed keys are only forgotten once all of them are
+            // applied, so applying them again after a panic is harmless.
+            for key in &self.changed
+            {
+                match data.get(key)
+                {
+                    Some(stored) =>
+                    {
+                        if !entries
+                            .get(key)
+                            .is_some_and(|frozen| frozen.is_current(stored))
+                        {
+                            entries.insert(key.clone(), Frozen::of(stored));
+                        }
+                    }
+                    None =>
+                    {
+                        entries.remove(key);
+                    }
+                }
+            }
+
+            self.changed.clear();
+        }
+
+        self.snapshot.clone()
+    }
+}
+
+impl<K, T> Default for Index<K, T>
+{
+    fn default() -> Self
+    {
+        Self {
+            snapshot: Snapshot::default(),
+            changed: BTreeSet::new(),
+        }
+    }
+}
+
+impl<K, T> Clone for Snapshot<K, T>
+{
+    fn clone(&self) -> Self
+    {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<K, T> Default for Snapshot<K, T>
+{
+    fn default() -> Self
+    {
+        Self {
+            entries: Arc::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl<'a, K, T> IntoIterator for &'a Snapshot<K, T>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a T);
+    type IntoIter = SnapshotIter<'a, K, T>;
+
+    fn into_iter(self) -> Self::IntoIter
+    {
+        self.iter()
+    }
+}
+
+/// An iterator over the keys and values of a snapshot, ordered by key.
+///
+/// Returned by [`Snapshot::iter`].
+pub struct SnapshotIter<'a, K, T>
+{
+    /// The entries of the snapshot.
+    inner: btree_map::Iter<'a, K, Frozen<T>>,
+}
+
+impl<'a, K, T> Iterator for SnapshotIter<'a, K, T>
+{
+    type Item = (&'a K, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        self.inner.next().map(|(key, frozen)| (key, &*frozen.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, T> ExactSizeIterator for SnapshotIter<'_, K, T> {}
+
+impl<K, T> FusedIterator for SnapshotIter<'_, K, T> {}
+
+impl<K, T, F> Store<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Take a snapshot of every entry in the store, see [`Snapshot`].
+    ///
+    /// Only the entries changed since the last snapshot are looked at, so
+    /// taking a snapshot of an unchanged store takes constant time.
+    pub fn snapshot(&self) -> Snapshot<K, T>
+    where
+        T: Clone,
+    {
+        lock::recover(self.index.lock()).refresh(&self.data)
+    }
+
+    /// Take a snapshot of the given entries, sharing unchanged values with
+    /// the last snapshot taken of this store.
+    ///
+    /// Every entry is looked at, for stores whose entries are kept elsewhere.
+    pub(super) fn snapshot_of<'a, I>(&self, entries: I) -> Snapshot<K, T>
+    where
+        T: Clone + 'a,
+        F: 'a,
+        K: 'a,
+        I: IntoIterator<Item = (&'a K, &'a Stored<T, F>)>,
+    {
+        let mut index = lock::recover(self.index.lock());
+        let snapshot = Snapshot::update(&index.snapshot, entries);
+
+        index.snapshot = snapshot.clone();
+        snapshot
+    }
+
+    /// Record that the entry with the given key may have changed since the
+    /// last snapshot.
+    pub(super) fn touch(&mut self, key: &K)
+    {
+        lock::recover(self.index.get_mut()).touch(key);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{Backend, Durability, LockMode, MemoryBackend, Options, Result};
+    use std::{path::Path, time::Duration};
+
+    /// Test that snapshots keep their entries and share unchanged values.
+    #[test]
+    fn test_snapshot() -> Result<()>
+    {
+        let options = Options::new().backend(MemoryBackend::new());
+        let mut store: Store<String, Vec<u32>> = Store::with_options("test", options)?;
+
+        store.save("a", vec![1])?;
+        store.save("b", vec![2])?;
+
+        let first = store.snapshot();
+
+        store.save("a", vec![3])?;
+        store.delete("b")?;
+        store.save("c", vec![4])?;
+
+        let second = store.snapshot();
+
+        assert_eq!(first.all(), vec![&vec![1], &vec![2]]);
+        assert_eq!(second.all(), vec![&vec![3], &vec![4]]);
+        assert_eq!(second.get_versioned("a"), store.get_versioned("a"));
+
+        let third = store.snapshot();
+
+        assert!(Arc::ptr_eq(&second.entries, &third.entries));
+
+        store.save("a", vec![5])?;
+
+        let fourth = store.snapshot();
+
+        assert!(Arc::ptr_eq(
+            &third.get_shared("c").unwrap(),
+            &fourth.get_shared("c").unwrap()
+        ));
+        assert_eq!(fourth.get("a"), Some(&vec![5]));
+        assert_eq!(third.get("a"), Some(&vec![3]));
+
+        Ok(())
+    }
+
+    /// Test that values modified in memory without being saved are not
+    /// shared with earlier snapshots.
+    #[test]
+    fn test_unsaved() -> Result<()>
+    {
+        let options = Options::new()
+            .backend(MemoryBackend::new())
+            .lock_timeout(Duration::ZERO);
+        let mut store: Store<String, Vec<u32>> = Store::with_options("test", options.clone())?;
+        let other: Store<String, Vec<u32>> = Store::with_options("test", options)?;
+
+        store.save("a", vec![1])?;
+
+        let first = store.snapshot();
+        let lock = other.try_lock(LockMode::Exclusive)?;
+
+        let mut guard = store.get_mut("a").unwrap();
+
+        guard.push(2);
+        assert!(guard.commit().is_err());
+
+        let second = store.snapshot();
+
+        assert_eq!(first.get("a"), Some(&vec![1]));
+        assert_eq!(second.get("a"), Some(&vec![1, 2]));
+
+        drop(lock);
+        store.get_mut("a").unwrap().push(3);
+
+        let third = store.snapshot();
+
+        assert_eq!(third.get("a"), Some(&vec![1, 2, 3]));
+        assert!(Arc::ptr_eq(
+            &third.get_shared("a").unwrap(),
+            &store.snapshot().get_shared("a").unwrap()
+        ));
+
+        Ok(())
+    }
+
+    /// Test that snapshots follow every kind of change to the store and are
+    /// not rebuilt while it is unchanged.
+    #[test]
+    fn test_changes() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, Vec<u32>> = Store::with_options("test", options)?;
+
+        store.save("a", vec![1])?;
+        store.save("b", vec![2])?;
+        store.save("c", vec![3])?;
+
+        let first = store.snapshot();
+
+        assert!(Arc::ptr_eq(&first.entries, &store.snapshot().entries));
+
+        store.entry("a")?.and_modify(|value| value.push(4))?;
+        store.retain(|key, _| key != "b")?;
+        backend.write_atomic(Path::new("test/d.json"), b"[5]", Durability::Full)?;
+        store.scan()?;
+
+        let second = store.snapshot();
+
+        assert_eq!(first.all(), vec![&vec![1], &vec![2], &vec![3]]);
+        assert_eq!(second.all(), vec![&vec![1, 4], &vec![3], &vec![5]]);
+        assert!(Arc::ptr_eq(
+            &first.get_shared("c").unwrap(),
+            &second.get_shared("c").unwrap()
+        ));
+
+        store.drain().for_each(drop);
+
+        assert!(store.snapshot().is_empty());
+        assert_eq!(second.len(), 3);
+
+        Ok(())
+    }
+}
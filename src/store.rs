@@ -1,149 +1,1868 @@
-use super::Stored;
-use anyhow::Result;
+use super::{
+    batch::{self, Batch},
+    entry::{Entry, OccupiedEntry, VacantEntry},
+    error::{Result, StoreError},
+    iter::{Drain, IntoIter, Iter, Keys, Values},
+    key::{self, StoreKey},
+    lock::{self, StoreLock},
+    manifest::{self, Manifest},
+    migration,
+    scan::{LoadFailure, ScanReport, SkipReason, Skipped},
+    snapshot::Index,
+    Durability, Fingerprint, Format, Json, MemoryBackend, Options, Order, RefMut, Stored, Version,
+};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs,
+    borrow::Borrow,
+    collections::{btree_map, BTreeMap},
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
 /// A store for storing data.
 ///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use store::Store;
 ///
-/// let mut store = Store::new("data");
+/// # fn main() -> store::Result<()> {
+/// let mut store: Store<String, String> = Store::new("data")?;
+///
+/// store.save("test", String::from("Hello, world!"))?;
+///
+/// let mut scores: Store<(String, u32), u64> = Store::new("scores")?;
 ///
-/// store.save("test", "Hello, world!")?;
+/// scores.save((String::from("alice"), 1), 100)?;
+/// # Ok(())
+/// # }
 /// ```
-pub struct Store<T>
+pub struct Store<K, T, F = Json>
 where
+    K: StoreKey,
     for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
 {
     /// The path to the store.
     pub(super) path: PathBuf,
-    /// The data stored in the store.
-    pub(super) data: HashMap<PathBuf, Stored<T>>,
+    /// The data stored in the store, ordered by key.
+    pub(super) data: BTreeMap<K, Stored<T, F>>,
+    /// The options the store was opened with.
+    pub(super) options: Options<F>,
+    /// The insertion order given to the last entry added to the store.
+    pub(super) sequence: u64,
+    /// What the store was created with.
+    pub(super) manifest: Manifest,
+    /// The last snapshot taken of the store and the keys changed since.
+    pub(super) index: Mutex<Index<K, T>>,
 }
 
-impl<T> Store<T>
+impl<K, T> Store<K, T>
 where
+    K: StoreKey,
     for<'de> T: Serialize + Deserialize<'de>,
 {
     /// Create a new store.
     pub fn new<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::with_options(path, Options::new())
+    }
+
+    /// Open a store, loading every entry that is already on disk.
+    ///
+    /// Files that cannot be read or deserialized do not abort the open, they
+    /// are reported alongside the store instead. See [`Store::scan`].
+    pub fn open<P>(path: P) -> Result<(Self, ScanReport<K>)>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_with(path, Options::new())
+    }
+}
+
+impl<K, T, F> Store<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Create a new store using the given options.
+    ///
+    /// A new store records its format, tag and schema version in a manifest.
+    /// Opening an existing store fails with [`StoreError::Mismatch`] if any of
+    /// them differ, see [`Manifest`].
+    ///
+    /// A read-only store waits for the store's lock while someone holds it
+    /// exclusively, see [`Options::read_only`].
+    pub fn with_options<P>(path: P, mut options: Options<F>) -> Result<Self>
     where
         P: AsRef<Path>,
     {
         let path = path.as_ref().to_path_buf();
 
-        fs::create_dir_all(&path)?;
+        options.store_lock = Some(Arc::new(StoreLock::new(&path, &options)));
+        options.backend.create_dir_all(&path)?;
+
+        let _held = lock::read(options.store_lock.as_ref())?;
+        let manifest = Manifest::open(&path, &options)?;
+
+        batch::recover(&path, &mut options)?;
 
         Ok(Self {
             path,
-            data: HashMap::new(),
+            data: BTreeMap::new(),
+            options,
+            sequence: 0,
+            manifest,
+            index: Mutex::default(),
         })
     }
 
-    /// Get all data from the store.
+    /// Create a new store using the given options and save every key and
+    /// value from an iterator into it.
+    ///
+    /// Stops at the first value that cannot be saved, see
+    /// [`Store::try_extend`].
+    pub fn with_entries<P, I, Q>(path: P, options: Options<F>, iter: I) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = (Q, T)>,
+        Q: Into<K>,
+    {
+        let mut store = Self::with_options(path, options)?;
+
+        store.try_extend(iter)?;
+
+        Ok(store)
+    }
+
+    /// Open a store using the given options.
+    ///
+    /// See [`Store::open`].
+    pub fn open_with<P>(path: P, options: Options<F>) -> Result<(Self, ScanReport<K>)>
+    where
+        P: AsRef<Path>,
+    {
+        let mut store = Self::with_options(path, options)?;
+        let (_, report) = store.scan()?;
+
+        Ok((store, report))
+    }
+
+    /// Scan the store's directory, loading entries that are on disk but not
+    /// yet in the store.
+    ///
+    /// Directories, temporary and hidden files, files of other formats and
+    /// files whose name does not decode into a key are skipped. Entries that
+    /// cannot be read or deserialized are reported rather than failing the
+    /// scan, and a missing directory is treated as empty. Entries already in
+    /// the store keep their current value, new entries are added in key order.
+    pub fn scan(&mut self) -> Result<(Vec<&T>, ScanReport<K>)>
+    {
+        let _held = lock::read(self.options.store_lock.as_ref())?;
+        let mut report = ScanReport::default();
+
+        let own = [
+            self.path.join(manifest::FILE_NAME),
+            self.path.join(batch::FILE_NAME),
+            self.path.join(lock::FILE_NAME),
+            self.path.join(lock::DIR_NAME),
+        ];
+        let mut paths = self.list(&self.path)?;
+
+        paths.retain(|path| !own.contains(path));
+
+        for _ in 1..K::SEGMENTS
+        {
+            paths = self.descend(paths)?;
+        }
+
+        paths.sort();
+
+        let mut found = Vec::new();
+
+        for path in paths
+        {
+            match self.classify(&path)
+            {
+                Classified::Entry(key) => found.push((key, path)),
+                Classified::Skipped(reason) => report.skipped.push(Skipped { path, reason }),
+                Classified::Failed(error) => report.failed.push(LoadFailure { path, error }),
+            }
+        }
+
+        found.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (key, path) in found
+        {
+            if !self.data.contains_key(&key)
+            {
+                match Stored::load_with(&path, &self.options)
+                {
+                    Ok(mut stored) =>
+                    {
+                        self.touch(&key);
+                        self.sequence += 1;
+                        stored.sequence = self.sequence;
+                        self.data.insert(key.clone(), stored);
+                        report.loaded.push(key.clone());
+                    }
+                    Err(error) =>
+                    {
+                        report.failed.push(LoadFailure { path, error });
+                        continue;
+                    }
+                }
+            }
+
+            report.entries.push(key);
+        }
+
+        report.missing = self
+            .data
+            .keys()
+            .filter(|key| !report.entries.contains(key))
+            .cloned()
+            .collect();
+
+        Ok((self.all(), report))
+    }
+
+    /// Get what the store was created with.
+    pub fn manifest(&self) -> &Manifest
+    {
+        &self.manifest
+    }
+
+    /// Set how durable saves to the store are.
+    pub fn with_durability(mut self, durability: Durability) -> Self
+    {
+        self.set_durability(durability);
+        self
+    }
+
+    /// Change how durable saves to the store are.
+    pub fn set_durability(&mut self, durability: Durability)
+    {
+        self.options.durability = durability;
+
+        for stored in self.data.values_mut()
+        {
+            stored.set_durability(durability);
+        }
+    }
+
+    /// Get how durable saves to the store are.
+    pub fn durability(&self) -> Durability
+    {
+        self.options.durability
+    }
+
+    /// Get all data from the store, ordered by key.
+    ///
+    /// Only entries loaded into the store are returned, use [`Store::scan`]
+    /// to pick up entries written by someone else.
     pub fn all(&self) -> Vec<&T>
     {
-        fs::read_dir(&self.path)
-            .unwrap()
-            .map(|entry| entry.unwrap().path())
-            .filter(|path| path.extension().unwrap() == "json")
-            .map(|path| self.data.get(&path).unwrap().value())
-            .collect()
+        self.data.values().map(Stored::value).collect()
     }
 
-    /// Save data to the store.
-    pub fn save(&mut self, name: &str, value: T) -> Result<()>
+    /// Get the keys and values in the store in the given order.
+    ///
+    /// Ordering by modification time reads the metadata of every file, and
+    /// fails if any of them cannot be read.
+    pub fn sorted_by(&self, order: Order) -> Result<Vec<(&K, &T)>>
     {
-        let path = self.path.join(name);
+        let mut entries: Vec<_> = self.iter().collect();
 
-        self.data
-            .entry(path.clone())
-            .or_insert_with(|| Stored::new(path, value).unwrap())
-            .save()?;
+        match order
+        {
+            Order::Key =>
+            {}
+            Order::Insertion => entries.sort_by_key(|(key, _)| self.data[*key].sequence),
+            Order::Modified =>
+            {
+                let mut modified = Vec::with_capacity(entries.len());
 
-        Ok(())
+                for (key, value) in entries
+                {
+                    let metadata = self.options.backend.metadata(&self.data[key].path)?;
+
+                    modified.push((metadata.modified, key, value));
+                }
+
+                modified.sort_by_key(|(modified, ..)| *modified);
+                entries = modified
+                    .into_iter()
+                    .map(|(_, key, value)| (key, value))
+                    .collect();
+            }
+        }
+
+        Ok(entries)
     }
 
-    /// Get data from the store.
-    pub fn get(&self, name: &str) -> Option<&T>
+    /// Get an iterator over the keys and values in the store, ordered by
+    /// key.
+    pub fn iter(&self) -> Iter<'_, K, T, F>
+    {
+        Iter {
+            inner: self.data.iter(),
+        }
+    }
+
+    /// Get an iterator over the keys in the store, in order.
+    pub fn keys(&self) -> Keys<'_, K, T, F>
     {
-        let path = self.path.join(name);
+        Keys { inner: self.iter() }
+    }
 
-        self.data.get(&path).map(|stored| stored.value())
+    /// Get an iterator over the values in the store, ordered by key.
+    pub fn values(&self) -> Values<'_, K, T, F>
+    {
+        Values { inner: self.iter() }
     }
 
-    /// Delete data from the store.
-    pub fn delete(&mut self, name: &str) -> Result<()>
+    /// Get the number of entries in the store.
+    pub fn len(&self) -> usize
     {
-        let path = self.path.join(name);
+        self.data.len()
+    }
+
+    /// Check whether the store has no entries.
+    pub fn is_empty(&self) -> bool
+    {
+        self.data.is_empty()
+    }
 
-        self.data.remove(&path).unwrap().delete()?;
+    /// Check whether the store has an entry with the given key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.data.contains_key(key)
+    }
 
-        if self.all().is_empty()
+    /// Delete every entry from the store.
+    ///
+    /// Stops at the first entry that cannot be deleted, which is kept in the
+    /// store along with the entries that were not reached yet.
+    pub fn clear(&mut self) -> Result<()>
+    {
+        for result in self.drain()
         {
-            fs::remove_dir_all(&self.path)?;
+            result?;
         }
 
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests
-{
-    use super::*;
+    /// Delete the entries for which `f` returns `false`.
+    ///
+    /// Stops at the first entry that cannot be deleted, which is kept in the
+    /// store along with the entries that were not reached yet.
+    pub fn retain<R>(&mut self, mut f: R) -> Result<()>
+    where
+        R: FnMut(&K, &T) -> bool,
+    {
+        let keys: Vec<_> = self
+            .iter()
+            .filter(|(key, value)| !f(key, value))
+            .map(|(key, _)| key.clone())
+            .collect();
 
-    /// Test that the store can save a value.
-    #[test]
-    fn test_store() -> Result<()>
+        for key in keys
+        {
+            self.touch(&key);
+            self.data[&key].delete()?;
+            self.data.remove(&key);
+        }
+
+        Ok(())
+    }
+
+    /// Delete every entry from the store, yielding their keys and values.
+    ///
+    /// See [`Drain`] for how errors and early drops are handled.
+    pub fn drain(&mut self) -> Drain<'_, K, T, F>
     {
-        let mut store: Store<String> = Store::new("test")?;
+        let keys: Vec<_> = self.data.keys().cloned().collect();
 
-        let name = "hello.json";
-        let value = String::from("world");
+        for key in &keys
+        {
+            self.touch(key);
+        }
 
-        store.save(name, value.clone())?;
+        Drain {
+            data: &mut self.data,
+            keys: keys.into_iter(),
+        }
+    }
 
-        assert_eq!(store.get(name), Some(&value));
+    /// Save every key and value from an iterator into the store.
+    ///
+    /// Stops at the first value that cannot be saved.
+    pub fn try_extend<I, Q>(&mut self, iter: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Q, T)>,
+        Q: Into<K>,
+    {
+        for (key, value) in iter
+        {
+            self.save(key, value)?;
+        }
 
-        store.delete(name)?;
+        Ok(())
+    }
 
+    /// Save data to the store, replacing any existing value.
+    pub fn save<Q>(&mut self, key: Q, value: T) -> Result<()>
+    where
+        Q: Into<K>,
+    {
+        self.replace(key, value)?;
         Ok(())
     }
 
-    /// Test that the store can save multiple values.
-    #[test]
-    fn test_all() -> Result<()>
+    /// Insert new data into the store.
+    ///
+    /// Fails with [`StoreError::AlreadyExists`] if the entry exists, either in
+    /// the store or as a file on disk, in which case nothing is written.
+    pub fn insert<Q>(&mut self, key: Q, value: T) -> Result<()>
+    where
+        Q: Into<K>,
     {
-        let mut store: Store<String> = Store::new("test")?;
+        let key = key.into();
+        let path = self.path_for(&key)?;
 
-        let entries = vec![
-            ("hello.json", String::from("world")),
-            ("goodbye.json", String::from("world")),
-        ];
+        if self.data.contains_key(&key)
+        {
+            return Err(StoreError::AlreadyExists { path });
+        }
 
-        for (name, value) in entries.iter()
+        self.touch(&key);
+        self.create_parent(&path)?;
+
+        match self.data.entry(key)
         {
-            store.save(name, value.clone())?;
+            btree_map::Entry::Occupied(_) => Err(StoreError::AlreadyExists { path }),
+            btree_map::Entry::Vacant(entry) =>
+            {
+                let mut stored = Stored::with_value(path, value, &self.options);
+
+                self.sequence += 1;
+                stored.sequence = self.sequence;
+                stored.create()?;
+                entry.insert(stored);
+
+                Ok(())
+            }
         }
+    }
 
-        let stored = store.all();
-        let compare = entries.iter().map(|(_, value)| value).collect::<Vec<_>>();
+    /// Update existing data in the store.
+    ///
+    /// Fails with [`StoreError::NotFound`] if the entry exists neither in the
+    /// store nor as a file on disk, in which case nothing is written. A file
+    /// the store has not loaded, such as one written by another process since
+    /// the store was scanned, is replaced and added to the store.
+    pub fn update<Q>(&mut self, key: Q, value: T) -> Result<()>
+    where
+        Q: Into<K>,
+    {
+        let key = key.into();
+        let path = self.path_for(&key)?;
 
-        assert_eq!(stored, compare);
+        self.touch(&key);
 
-        for (name, _) in entries.iter()
+        match self.data.get_mut(&key)
+        {
+            Some(stored) => stored.store(value),
+            None =>
+            {
+                let _held = lock::write(self.options.store_lock.as_ref())?;
+
+                if !self.options.backend.exists(&path)
+                {
+                    return Err(StoreError::NotFound { path });
+                }
+
+                self.write_checked(key, path, value, false)?;
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Save data to the store, returning the value it replaced.
+    ///
+    /// The file is written before the store is changed, so on error the
+    /// store keeps its previous value.
+    pub fn replace<Q>(&mut self, key: Q, value: T) -> Result<Option<T>>
+    where
+        Q: Into<K>,
+    {
+        let key = key.into();
+        let path = self.path_for(&key)?;
+
+        self.touch(&key);
+        self.create_parent(&path)?;
+
+        match self.data.entry(key)
+        {
+            btree_map::Entry::Occupied(mut entry) => Ok(Some(entry.get_mut().replace(value)?)),
+            btree_map::Entry::Vacant(entry) =>
+            {
+                let mut stored = Stored::with_value(path, value, &self.options);
+
+                self.sequence += 1;
+                stored.sequence = self.sequence;
+                stored.save()?;
+                entry.insert(stored);
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// Start a batch of saves and deletes that are committed as a single
+    /// unit, see [`Batch`].
+    pub fn batch(&mut self) -> Batch<'_, K, T, F>
+    {
+        Batch::new(self, BTreeMap::new())
+    }
+
+    /// Get the entry with the given key for in-place manipulation.
+    pub fn entry<Q>(&mut self, key: Q) -> Result<Entry<'_, K, T, F>>
+    where
+        Q: Into<K>,
+    {
+        let key = key.into();
+        let path = self.path_for(&key)?;
+
+        self.touch(&key);
+        self.create_parent(&path)?;
+
+        Ok(match self.data.entry(key)
+        {
+            btree_map::Entry::Occupied(entry) => Entry::Occupied(OccupiedEntry { entry }),
+            btree_map::Entry::Vacant(entry) =>
+            {
+                self.sequence += 1;
+
+                Entry::Vacant(VacantEntry {
+                    entry,
+                    path,
+                    sequence: self.sequence,
+                    options: &self.options,
+                })
+            }
+        })
+    }
+
+    /// Get data from the store, saving the value computed by `f` if the entry
+    /// does not exist.
+    pub fn get_or_insert_with<Q, D>(&mut self, key: Q, f: D) -> Result<&T>
+    where
+        Q: Into<K>,
+        D: FnOnce() -> T,
+    {
+        let key = key.into();
+        let path = self.path_for(&key)?;
+
+        self.touch(&key);
+        self.create_parent(&path)?;
+
+        match self.data.entry(key)
+        {
+            btree_map::Entry::Occupied(entry) => Ok(entry.into_mut().value()),
+            btree_map::Entry::Vacant(entry) =>
+            {
+                let mut stored = Stored::with_value(path, f(), &self.options);
+
+                self.sequence += 1;
+                stored.sequence = self.sequence;
+                stored.save()?;
+
+                Ok(entry.insert(stored).value())
+            }
+        }
+    }
+
+    /// Get data from the store.
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.data.get(key).map(|stored| stored.value())
+    }
+
+    /// Get mutable access to data in the store.
+    ///
+    /// The returned guard saves the value when it is dropped if it was
+    /// modified, or explicitly through [`RefMut::commit`].
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<RefMut<'_, T, F>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let key = self.data.get_key_value(key)?.0.clone();
+
+        self.touch(&key);
+        self.data.get_mut::<K>(&key).map(Stored::get_mut)
+    }
+
+    /// Get data from the store along with the version of its file, for use
+    /// with [`Store::save_if_version`].
+    pub fn get_versioned<Q>(&self, key: &Q) -> Option<(&T, Version)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let stored = self.data.get(key)?;
+
+        Some((stored.value(), stored.version()?))
+    }
+
+    /// Save data to the store only if its file is still at the `expected`
+    /// version, or does not exist if `expected` is `None`. Returns the new
+    /// version.
+    ///
+    /// Fails with [`StoreError::Conflict`] if the file was changed, in which
+    /// case nothing is written. The check and the write both happen while
+    /// holding the store's lock, so only writers that do not lock the store
+    /// can slip in between, see [`lock`].
+    pub fn save_if_version<Q>(
+        &mut self,
+        key: Q,
+        value: T,
+        expected: Option<Version>,
+    ) -> Result<Version>
+    where
+        Q: Into<K>,
+    {
+        let key = key.into();
+        let path = self.path_for(&key)?;
+        let _held = lock::write(self.options.store_lock.as_ref())?;
+        let found = self.read_version(&path)?;
+
+        if found != expected
+        {
+            return Err(StoreError::Conflict { path, found });
+        }
+
+        self.write_checked(key, path, value, expected.is_none())
+    }
+
+    /// Replace data in the store only if its file still holds `current`, or
+    /// does not exist if `current` is `None`. Returns the new version.
+    ///
+    /// Unlike [`Store::save_if_version`] this compares values rather than
+    /// versions, so it also succeeds if the file was rewritten with an equal
+    /// value. Fails with [`StoreError::Conflict`] otherwise.
+    pub fn compare_and_swap<Q>(&mut self, key: Q, current: Option<&T>, new: T) -> Result<Version>
+    where
+        Q: Into<K>,
+        T: PartialEq,
+    {
+        let key = key.into();
+        let path = self.path_for(&key)?;
+        let _held = lock::write(self.options.store_lock.as_ref())?;
+
+        let (found, version) = match self.options.backend.read(&path)
+        {
+            Ok(bytes) =>
+            {
+                let (value, _) = migration::decode::<T, F>(&path, &bytes, &self.options)?;
+
+                (Some(value), Some(Version::of(&bytes)))
+            }
+            Err(StoreError::NotFound { .. }) => (None, None),
+            Err(error) => return Err(error),
+        };
+
+        if found.as_ref() != current
+        {
+            return Err(StoreError::Conflict {
+                path,
+                found: version,
+            });
+        }
+
+        self.write_checked(key, path, new, current.is_none())
+    }
+
+    /// Delete data from the store.
+    pub fn delete<Q>(&mut self, key: &Q) -> Result<()>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = K> + ?Sized,
+    {
+        let path = self.path_for(&key.to_owned())?;
+        let _held = lock::write(self.options.store_lock.as_ref())?;
+
+        self.touch(&key.to_owned());
+        self.data
+            .remove(key)
+            .ok_or_else(|| StoreError::NotFound { path: path.clone() })?
+            .delete()?;
+
+        self.prune(&path)
+    }
+
+    /// Get a store rooted at a namespace nested inside this store.
+    ///
+    /// The namespace is split on `/` and every part is encoded like a string
+    /// key, so `users/42` maps to the directory `users/42` inside the store.
+    /// The returned store shares this store's options, lock and manifest, so
+    /// locking this store also keeps out writes through the scoped store. It
+    /// starts empty, use [`Store::scan`] to load its entries.
+    pub fn scope(&self, namespace: &str) -> Result<Self>
+    {
+        let mut path = self.path.clone();
+
+        for part in namespace.split('/')
+        {
+            let part = key::encode(part)?;
+
+            key::check(&part)?;
+            path.push(part);
+        }
+
+        let mut options = self.options.clone();
+
+        options.backend.create_dir_all(&path)?;
+
+        let _held = lock::read(options.store_lock.as_ref())?;
+
+        batch::recover(&path, &mut options)?;
+
+        Ok(Self {
+            path,
+            data: BTreeMap::new(),
+            options,
+            sequence: 0,
+            manifest: self.manifest.clone(),
+            index: Mutex::default(),
+        })
+    }
+
+    /// Get the namespaces directly inside this store.
+    ///
+    /// Directories whose name is not an encoded namespace are ignored.
+    pub fn namespaces(&self) -> Result<Vec<String>>
+    {
+        let mut namespaces = Vec::new();
+
+        for path in self.list(&self.path)?
+        {
+            if !self.options.backend.metadata(&path)?.is_dir || is_hidden(&path)
+            {
+                continue;
+            }
+
+            if let Some(Ok(namespace)) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(key::decode)
+            {
+                namespaces.push(namespace);
+            }
+        }
+
+        namespaces.sort();
+
+        Ok(namespaces)
+    }
+
+    /// List the entries on disk in this store and every namespace inside it,
+    /// without loading them.
+    ///
+    /// Every entry is returned with its namespace relative to this store, which
+    /// is empty for the store's own entries. Files that are not entries are
+    /// ignored, use [`Store::scan`] on a scoped store to find out why.
+    pub fn walk(&self) -> Result<Vec<(String, K)>>
+    {
+        let mut entries = Vec::new();
+        let mut dirs = vec![self.path.clone()];
+
+        while let Some(dir) = dirs.pop()
         {
-            store.delete(name)?;
+            for path in self.list(&dir)?
+            {
+                if self.options.backend.metadata(&path)?.is_dir
+                {
+                    if !is_hidden(&path)
+                    {
+                        dirs.push(path);
+                    }
+
+                    continue;
+                }
+
+                let Some(segments) = self.segments(&path)
+                else
+                {
+                    continue;
+                };
+
+                if segments.len() < K::SEGMENTS
+                {
+                    continue;
+                }
+
+                let (namespace, stem) = segments.split_at(segments.len() - K::SEGMENTS);
+                let namespace: Result<Vec<_>> =
+                    namespace.iter().map(|part| key::decode(part)).collect();
+
+                if let (Ok(namespace), Ok(key)) = (namespace, K::decode(&stem.join("/")))
+                {
+                    entries.push((namespace.join("/"), key));
+                }
+            }
         }
 
+        entries.sort();
+
+        Ok(entries)
+    }
+
+    /// Get the path of the file backing an entry.
+    ///
+    /// Fails with [`StoreError::InvalidKey`] if the key cannot be encoded
+    /// into a safe file name.
+    pub(super) fn path_for(&self, key: &K) -> Result<PathBuf>
+    {
+        let stem = key.encode()?;
+        let segments: Vec<_> = stem.split('/').collect();
+
+        if segments.len() != K::SEGMENTS
+        {
+            return Err(StoreError::InvalidKey {
+                key: stem,
+                reason: "wrong number of path segments",
+            });
+        }
+
+        let mut path = self.path.clone();
+
+        for (index, segment) in segments.iter().enumerate()
+        {
+            key::check(segment)?;
+
+            if index + 1 < segments.len()
+            {
+                path.push(segment);
+            }
+            else
+            {
+                path.push(format!("{}.{}", segment, self.options.format.extension()));
+            }
+        }
+
+        Ok(path)
+    }
+
+    /// Get the key of an entry's file, or `None` if the path is not one.
+    pub(super) fn key_for(&self, path: &Path) -> Option<K>
+    {
+        K::decode(&self.segments(path)?.join("/")).ok()
+    }
+
+    /// Write an entry whose file was just checked, creating the file if
+    /// `create` is set and replacing it otherwise.
+    fn write_checked(&mut self, key: K, path: PathBuf, value: T, create: bool) -> Result<Version>
+    {
+        let mut stored = Stored::with_value(path, value, &self.options);
+        let bytes = stored.serialize(stored.value())?;
+        let backend = &self.options.backend;
+        let durability = self.options.durability;
+
+        self.create_parent(&stored.path)?;
+
+        let written = if create
+        {
+            backend.write_new(&stored.path, &bytes, durability)
+        }
+        else
+        {
+            backend.write_atomic(&stored.path, &bytes, durability)
+        };
+
+        match written
+        {
+            Ok(()) => (),
+            Err(StoreError::AlreadyExists { path }) =>
+            {
+                let found = self.read_version(&path)?;
+
+                return Err(StoreError::Conflict { path, found });
+            }
+            Err(error) => return Err(error),
+        }
+
+        let fingerprint = Fingerprint::of(&**backend, &stored.path, &bytes);
+
+        stored.fingerprint = Some(fingerprint);
+        self.touch(&key);
+
+        match self.data.entry(key)
+        {
+            btree_map::Entry::Occupied(mut entry) =>
+            {
+                stored.sequence = entry.get().sequence;
+                entry.insert(stored);
+            }
+            btree_map::Entry::Vacant(entry) =>
+            {
+                self.sequence += 1;
+                stored.sequence = self.sequence;
+                entry.insert(stored);
+            }
+        }
+
+        Ok(fingerprint.version)
+    }
+
+    /// Read the version of an entry's file, or `None` if it does not exist.
+    fn read_version(&self, path: &Path) -> Result<Option<Version>>
+    {
+        match self.options.backend.read(path)
+        {
+            Ok(bytes) => Ok(Some(Version::of(&bytes))),
+            Err(StoreError::NotFound { .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Create the directories an entry is nested in.
+    pub(super) fn create_parent(&self, path: &Path) -> Result<()>
+    {
+        match path.parent()
+        {
+            Some(parent) if K::SEGMENTS > 1 => self.options.backend.create_dir_all(parent),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove the now empty directories an entry was nested in, up to but
+    /// not including the store's own directory.
+    pub(super) fn prune(&self, path: &Path) -> Result<()>
+    {
+        let mut dir = path.parent();
+
+        while let Some(current) = dir.filter(|dir| dir.starts_with(&self.path) && *dir != self.path)
+        {
+            if !self.list(current)?.is_empty()
+            {
+                break;
+            }
+
+            self.options.backend.remove_dir_all(current)?;
+            dir = current.parent();
+        }
+
+        Ok(())
+    }
+
+    /// List a directory, treating a missing directory as empty.
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>>
+    {
+        match self.options.backend.list(dir)
+        {
+            Ok(paths) => Ok(paths),
+            Err(StoreError::NotFound { .. }) => Ok(Vec::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Replace every directory in `paths` by its contents, to descend one
+    /// level into the directories of nested keys.
+    fn descend(&self, paths: Vec<PathBuf>) -> Result<Vec<PathBuf>>
+    {
+        let mut descended = Vec::new();
+
+        for path in paths
+        {
+            match self.options.backend.metadata(&path)
+            {
+                Ok(metadata) if metadata.is_dir && !is_hidden(&path) =>
+                {
+                    descended.extend(self.list(&path)?)
+                }
+                _ => descended.push(path),
+            }
+        }
+
+        Ok(descended)
+    }
+
+    /// Get the path segments of a file relative to the store, with the
+    /// extension of the store's format removed from the last one.
+    fn segments(&self, path: &Path) -> Option<Vec<String>>
+    {
+        let mut segments = path
+            .strip_prefix(&self.path)
+            .ok()?
+            .iter()
+            .map(|part| part.to_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()?;
+
+        let file_name = segments.pop()?;
+
+        if file_name.starts_with('.')
+        {
+            return None;
+        }
+
+        let stem = file_name
+            .strip_suffix(self.options.format.extension())?
+            .strip_suffix('.')?;
+
+        segments.push(stem.to_string());
+
+        Some(segments)
+    }
+
+    /// Determine whether a path in the store's directory is an entry.
+    fn classify(&self, path: &Path) -> Classified<K>
+    {
+        let metadata = match self.options.backend.metadata(path)
+        {
+            Ok(metadata) => metadata,
+            Err(error) => return Classified::Failed(error),
+        };
+
+        if metadata.is_dir
+        {
+            return Classified::Skipped(SkipReason::Directory);
+        }
+
+        let file_name = match path.file_name().and_then(|name| name.to_str())
+        {
+            Some(file_name) => file_name,
+            None => return Classified::Skipped(SkipReason::InvalidName),
+        };
+
+        if file_name.starts_with('.') && file_name.ends_with(".tmp")
+        {
+            return Classified::Skipped(SkipReason::Temporary);
+        }
+
+        if file_name.starts_with('.')
+        {
+            return Classified::Skipped(SkipReason::Hidden);
+        }
+
+        let has_extension = file_name
+            .strip_suffix(self.options.format.extension())
+            .is_some_and(|stem| stem.ends_with('.'));
+
+        if !has_extension
+        {
+            return Classified::Skipped(SkipReason::Extension);
+        }
+
+        match self
+            .segments(path)
+            .map(|segments| K::decode(&segments.join("/")))
+        {
+            Some(Ok(key)) => Classified::Entry(key),
+            _ => Classified::Skipped(SkipReason::InvalidName),
+        }
+    }
+}
+
+impl<'a, K, T, F> IntoIterator for &'a Store<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    type Item = (&'a K, &'a T);
+    type IntoIter = Iter<'a, K, T, F>;
+
+    fn into_iter(self) -> Self::IntoIter
+    {
+        self.iter()
+    }
+}
+
+impl<K, T, F> IntoIterator for Store<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    type Item = (K, T);
+    type IntoIter = IntoIter<K, T, F>;
+
+    /// Consume the store into its keys and values, leaving the files on
+    /// disk untouched.
+    fn into_iter(self) -> Self::IntoIter
+    {
+        IntoIter {
+            inner: self.data.into_iter(),
+        }
+    }
+}
+
+impl<Q, K, T, F> Extend<(Q, T)> for Store<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+    Q: Into<K>,
+{
+    /// Save every key and value from an iterator into the store.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value cannot be saved, use [`Store::try_extend`] to handle
+    /// the error instead.
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (Q, T)>,
+    {
+        if let Err(error) = self.try_extend(iter)
+        {
+            panic!("failed to extend store: {}", error);
+        }
+    }
+}
+
+impl<Q, K, T, F> FromIterator<(Q, T)> for Store<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format + Default,
+    Q: Into<K>,
+{
+    /// Collect keys and values into a new store kept in memory.
+    ///
+    /// The store lives at `/` in a [`MemoryBackend`] of its own, so its
+    /// entries are never written to disk and are gone once it is dropped. Use
+    /// [`Store::with_entries`] to collect them into a store on disk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value cannot be saved, such as when a key cannot be
+    /// encoded.
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (Q, T)>,
+    {
+        let options = Options::new()
+            .backend(MemoryBackend::new())
+            .format(F::default());
+        let mut store = Self::with_options("/", options).expect("failed to create store in memory");
+
+        store.extend(iter);
+        store
+    }
+}
+
+/// How a path in a store's directory was classified while scanning.
+enum Classified<K>
+{
+    /// The path is the entry with the given key.
+    Entry(K),
+    /// The path is not an entry.
+    Skipped(SkipReason),
+    /// The path could not be inspected.
+    Failed(StoreError),
+}
+
+/// Check whether a path names a hidden file or directory, such as those the
+/// store keeps its own state in.
+fn is_hidden(path: &Path) -> bool
+{
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{Backend, JsonLines, LockMode, Migrations, Nested, PrettyJson};
+    use std::time::Duration;
+
+    /// Get options storing files in memory.
+    fn memory() -> Options
+    {
+        Options::new().backend(MemoryBackend::new())
+    }
+
+    /// Test that the store can save a value.
+    #[test]
+    fn test_store() -> Result<()>
+    {
+        let mut store: Store<String, String> = Store::with_options("test", memory())?;
+
+        let name = "hello.json";
+        let value = String::from("world");
+
+        store.save(name, value.clone())?;
+
+        assert_eq!(store.get(name), Some(&value));
+
+        store.delete(name)?;
+
+        Ok(())
+    }
+
+    /// Test that the store can save multiple values.
+    #[test]
+    fn test_all() -> Result<()>
+    {
+        let mut store: Store<String, String> = Store::with_options("test", memory())?;
+
+        let entries = [
+            ("hello.json", String::from("world")),
+            ("goodbye.json", String::from("world")),
+        ];
+
+        for (name, value) in entries.iter()
+        {
+            store.save(*name, value.clone())?;
+        }
+
+        let stored = store
+            .sorted_by(Order::Insertion)?
+            .into_iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect::<Vec<_>>();
+        let compare = entries
+            .iter()
+            .map(|(name, value)| (*name, value))
+            .collect::<Vec<_>>();
+
+        assert_eq!(stored, compare);
+
+        for (name, _) in entries.iter()
+        {
+            store.delete(*name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Test that opening a store loads existing entries and reports broken ones.
+    #[test]
+    fn test_open() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, String> = Store::with_options("test", options.clone())?;
+
+        store.save("hello", String::from("world"))?;
+        drop(store);
+
+        backend.write_atomic(
+            Path::new("test/broken.json"),
+            b"{ not json",
+            Durability::None,
+        )?;
+
+        let (store, report) = Store::<String, String>::open_with("test", options)?;
+
+        assert_eq!(store.get("hello"), Some(&String::from("world")));
+        assert_eq!(report.failed.len(), 1);
+        assert!(matches!(report.failed[0].error, StoreError::Corrupt { .. }));
+        assert_eq!(report.failed[0].path, Path::new("test/broken.json"));
+
+        Ok(())
+    }
+
+    /// Test that a store uses the extension and encoding of its format.
+    #[test]
+    fn test_format() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+
+        let options = Options::new().backend(backend.clone()).format(PrettyJson);
+        let mut store: Store<String, Vec<u32>, _> = Store::with_options("test", options)?;
+
+        store.save("numbers", vec![1, 2])?;
+
+        assert_eq!(
+            backend.read(Path::new("test/numbers.json"))?,
+            b"[\n  1,\n  2\n]\n"
+        );
+
+        let options = Options::new().backend(backend.clone()).format(JsonLines);
+        let mut store: Store<String, Vec<u32>, _> = Store::with_options("lines", options.clone())?;
+
+        store.save("numbers", vec![3, 4])?;
+
+        let (store, report) = Store::<String, Vec<u32>, _>::open_with("lines", options)?;
+
+        assert!(report.is_clean());
+        assert_eq!(store.all(), vec![&vec![3, 4]]);
+        assert_eq!(backend.read(Path::new("lines/numbers.jsonl"))?, b"3\n4\n");
+
+        Ok(())
+    }
+
+    /// Test that saving an existing entry replaces its value.
+    #[test]
+    fn test_save_replaces() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, String> = Store::with_options("test", options)?;
+
+        store.save("hello", String::from("world"))?;
+        store.save("hello", String::from("there"))?;
+
+        assert_eq!(store.get("hello"), Some(&String::from("there")));
+        assert_eq!(backend.read(Path::new("test/hello.json"))?, b"\"there\"");
+
+        Ok(())
+    }
+
+    /// Test the insert, update and replace operations.
+    #[test]
+    fn test_insert_update_replace() -> Result<()>
+    {
+        let options = memory();
+        let mut store: Store<String, String> = Store::with_options("test", options.clone())?;
+        let mut other: Store<String, String> = Store::with_options("test", options.clone())?;
+
+        assert!(matches!(
+            store.update("hello", String::from("world")),
+            Err(StoreError::NotFound { .. })
+        ));
+
+        store.insert("hello", String::from("world"))?;
+
+        assert!(matches!(
+            store.insert("hello", String::from("there")),
+            Err(StoreError::AlreadyExists { .. })
+        ));
+
+        assert!(matches!(
+            other.insert("hello", String::from("there")),
+            Err(StoreError::AlreadyExists { .. })
+        ));
+
+        other.update("hello", String::from("there"))?;
+
+        assert_eq!(other.get("hello"), Some(&String::from("there")));
+
+        let (mut store, _) = Store::<String, String>::open_with("test", options)?;
+
+        assert_eq!(
+            store.replace("hello", String::from("again"))?,
+            Some(String::from("there"))
+        );
+        assert_eq!(store.replace("goodbye", String::from("world"))?, None);
+        assert_eq!(store.get_or_insert_with("hello", String::new)?, "again");
+        assert_eq!(
+            store.get_or_insert_with("new", || String::from("value"))?,
+            "value"
+        );
+        assert_eq!(store.get("new"), Some(&String::from("value")));
+
+        Ok(())
+    }
+
+    /// Test the entry API.
+    #[test]
+    fn test_entry() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, u32> = Store::with_options("test", options)?;
+
+        assert_eq!(*store.entry("visits")?.or_default()?, 0);
+
+        for _ in 0..2
+        {
+            store
+                .entry("visits")?
+                .and_modify(|visits| *visits += 1)?
+                .or_insert(1)?;
+        }
+
+        assert_eq!(store.get("visits"), Some(&2));
+        assert_eq!(backend.read(Path::new("test/visits.json"))?, b"2");
+
+        match store.entry("visits")?
+        {
+            Entry::Occupied(entry) => assert_eq!(entry.remove()?, 2),
+            Entry::Vacant(_) => panic!("entry should be occupied"),
+        }
+
+        assert_eq!(store.get("visits"), None);
+        assert!(!backend.exists(Path::new("test/visits.json")));
+
+        Ok(())
+    }
+
+    /// Test that mutable access saves the value when the guard is dropped.
+    #[test]
+    fn test_get_mut() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, Vec<String>> = Store::with_options("test", options)?;
+
+        store.save("names", Vec::new())?;
+
+        if let Some(mut names) = store.get_mut("names")
+        {
+            names.push(String::from("Milan"));
+        }
+
+        assert_eq!(store.get("names"), Some(&vec![String::from("Milan")]));
+        assert_eq!(backend.read(Path::new("test/names.json"))?, b"[\"Milan\"]");
+        assert!(store.get_mut("missing").is_none());
+
+        Ok(())
+    }
+
+    /// Test that names cannot escape the store's directory.
+    #[test]
+    fn test_key_encoding() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, String> = Store::with_options("test", options.clone())?;
+
+        store.save("../escape", String::from("contained"))?;
+        store.save("a/b", String::from("nested"))?;
+
+        assert!(backend.exists(Path::new("test/%2E.%2Fescape.json")));
+        assert!(backend.exists(Path::new("test/a%2Fb.json")));
+        assert!(matches!(
+            store.save("", String::new()),
+            Err(StoreError::InvalidKey { .. })
+        ));
+
+        let (store, report) = Store::<String, String>::open_with("test", options)?;
+
+        assert!(report.is_clean());
+        assert_eq!(store.get("../escape"), Some(&String::from("contained")));
+        assert_eq!(store.get("a/b"), Some(&String::from("nested")));
+
+        Ok(())
+    }
+
+    /// Test that scanning skips and reports files that are not entries.
+    #[test]
+    fn test_scan() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, String> = Store::with_options("test", options)?;
+
+        store.save("hello", String::from("world"))?;
+        store.save("gone", String::from("away"))?;
+
+        let write = |path: &str, bytes: &[u8]| {
+            backend.write_atomic(Path::new(path), bytes, Durability::None)
+        };
+
+        backend.create_dir_all(Path::new("test/nested.json"))?;
+        backend.remove(Path::new("test/gone.json"))?;
+        write("test/.hidden.json", b"\"hidden\"")?;
+        write("test/.hello.json.1.0.tmp", b"\"partial")?;
+        write("test/README", b"foreign")?;
+        write("test/%zz.json", b"\"invalid\"")?;
+        write("test/other.json", b"\"external\"")?;
+        write("test/broken.json", b"{ not json")?;
+
+        let (values, report) = store.scan()?;
+        let skipped = |reason| {
+            report
+                .skipped
+                .iter()
+                .filter(|skipped| skipped.reason == reason)
+                .count()
+        };
+
+        assert_eq!(values.len(), 3);
+        assert_eq!(report.loaded, vec![String::from("other")]);
+        assert_eq!(report.missing, vec![String::from("gone")]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(skipped(SkipReason::Directory), 1);
+        assert_eq!(skipped(SkipReason::Hidden), 1);
+        assert_eq!(skipped(SkipReason::Temporary), 1);
+        assert_eq!(skipped(SkipReason::Extension), 1);
+        assert_eq!(skipped(SkipReason::InvalidName), 1);
+        assert_eq!(store.get("other"), Some(&String::from("external")));
+
+        Ok(())
+    }
+
+    /// Test that a store can be iterated, filtered, drained and collected.
+    #[test]
+    fn test_collection() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, u32> = Store::with_options("test", options.clone())?;
+
+        store.extend([("one", 1), ("two", 2), ("three", 3)]);
+
+        let mut keys: Vec<_> = store.keys().collect();
+        keys.sort();
+
+        assert_eq!(keys, vec!["one", "three", "two"]);
+        assert_eq!(store.values().sum::<u32>(), 6);
+        assert_eq!(store.iter().len(), 3);
+        assert!(store.contains_key("two"));
+
+        store.retain(|_, value| value % 2 == 1)?;
+
+        assert_eq!(store.len(), 2);
+        assert!(!store.contains_key("two"));
+        assert!(!backend.exists(Path::new("test/two.json")));
+
+        let mut drained = store.drain().collect::<Result<Vec<_>>>()?;
+        drained.sort();
+
+        assert_eq!(
+            drained,
+            vec![(String::from("one"), 1), (String::from("three"), 3)]
+        );
+        assert!(store.is_empty());
+        assert_eq!(
+            backend.list(Path::new("test"))?,
+            vec![PathBuf::from("test/.store.json")]
+        );
+
+        let store: Store<String, u32> =
+            Store::with_entries("other", options, [("a", 1), ("b", 2)])?;
+
+        assert!(backend.exists(Path::new("other/b.json")));
+
+        let mut entries: Vec<_> = store.into_iter().collect();
+        entries.sort();
+
+        assert_eq!(
+            entries,
+            vec![(String::from("a"), 1), (String::from("b"), 2)]
+        );
+
+        let store: Store<String, u32> = [("c", 3)].into_iter().collect();
+
+        assert_eq!(store.get("c"), Some(&3));
+        assert!(!backend.exists(Path::new("/c.json")));
+
+        Ok(())
+    }
+
+    /// Test that entries are visited by key, insertion or modification time.
+    #[test]
+    fn test_order() -> Result<()>
+    {
+        let s = String::from;
+        let mut store: Store<String, u32> = Store::with_options("test", memory())?;
+
+        for (name, value) in [("b", 1), ("c", 2), ("a", 3)]
+        {
+            store.save(name, value)?;
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        store.save("b", 4)?;
+
+        assert_eq!(store.keys().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert_eq!(
+            store.sorted_by(Order::Key)?,
+            vec![(&s("a"), &3), (&s("b"), &4), (&s("c"), &2)]
+        );
+        assert_eq!(
+            store.sorted_by(Order::Insertion)?,
+            vec![(&s("b"), &4), (&s("c"), &2), (&s("a"), &3)]
+        );
+        assert_eq!(
+            store.sorted_by(Order::Modified)?,
+            vec![(&s("c"), &2), (&s("a"), &3), (&s("b"), &4)]
+        );
+        assert_eq!(store.all(), vec![&3, &4, &2]);
+
+        Ok(())
+    }
+
+    /// Test that integer and tuple keys are stored and scanned back typed.
+    #[test]
+    fn test_typed_keys() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<u32, String> = Store::with_options("test", options.clone())?;
+
+        store.save(10u32, String::from("ten"))?;
+        store.save(9u32, String::from("nine"))?;
+
+        assert_eq!(store.keys().collect::<Vec<_>>(), vec![&9, &10]);
+        assert_eq!(store.get(&10), Some(&String::from("ten")));
+        assert!(backend.exists(Path::new("test/10.json")));
+
+        backend.write_atomic(Path::new("test/010.json"), b"\"x\"", Durability::None)?;
+
+        let (store, report) = Store::<u32, String>::open_with("test", options)?;
+
+        assert_eq!(report.entries, vec![9, 10]);
+        assert_eq!(report.skipped[0].reason, SkipReason::InvalidName);
+        assert_eq!(store.len(), 2);
+
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<(String, u8), u32> = Store::with_options("pairs", options)?;
+
+        store.save((String::from("a"), 1), 1)?;
+
+        assert_eq!(store.get(&(String::from("a"), 1)), Some(&1));
+        assert!(backend.exists(Path::new("pairs/a,1.json")));
+
+        Ok(())
+    }
+
+    /// Test that scoped stores and nested keys are organised into directories.
+    #[test]
+    fn test_scope() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new()
+            .backend(backend.clone())
+            .lock_timeout(Duration::ZERO);
+        let mut store: Store<String, u32> = Store::with_options("test", options.clone())?;
+        let mut scope = store.scope("users/42")?;
+
+        store.save("root", 1)?;
+        scope.save("visits", 2)?;
+
+        assert!(backend.exists(Path::new("test/users/42/visits.json")));
+        assert!(!backend.exists(Path::new("test/users/42/.store.json")));
+
+        let other: Store<String, u32> = Store::with_options("test", options.clone())?;
+        let exclusive = other.try_lock(LockMode::Exclusive)?;
+
+        assert!(matches!(
+            scope.save("visits", 3),
+            Err(StoreError::Locked { .. })
+        ));
+
+        drop(exclusive);
+
+        let exclusive = store.try_lock(LockMode::Exclusive)?;
+
+        scope.delete("visits")?;
+        scope.save("visits", 2)?;
+        drop(exclusive);
+
+        assert_eq!(store.namespaces()?, vec![String::from("users")]);
+        assert_eq!(
+            store.walk()?,
+            vec![
+                (String::new(), String::from("root")),
+                (String::from("users/42"), String::from("visits")),
+            ]
+        );
+        assert!(store
+            .scope("users/../..")
+            .is_ok_and(|scope| scope.path.starts_with("test")));
+        assert!(store.scope("users//42").is_err());
+
+        store.delete("root")?;
+
+        assert!(backend.exists(Path::new("test/users/42/visits.json")));
+
+        let mut nested: Store<Nested<(String, u32)>, u32> =
+            Store::with_options("nested", options.clone())?;
+
+        nested.save((String::from("users"), 7), 3)?;
+        backend.create_dir_all(Path::new("nested/users/8"))?;
+
+        assert!(backend.exists(Path::new("nested/users/7.json")));
+
+        let (nested, report) = Store::<Nested<(String, u32)>, u32>::open_with("nested", options)?;
+
+        assert_eq!(nested.get(&Nested((String::from("users"), 7))), Some(&3));
+        assert_eq!(report.skipped[0].reason, SkipReason::Directory);
+
+        Ok(())
+    }
+
+    /// Test that a store refuses to open with another tag, format or schema.
+    #[test]
+    fn test_manifest() -> Result<()>
+    {
+        let options = memory().schema_version(2);
+        let store: Store<String, u32> = Store::with_options("test", options.clone())?;
+
+        assert_eq!(store.manifest().schema_version, 2);
+        assert_eq!(store.manifest().format, "json");
+        assert_eq!(store.manifest().tag, None);
+
+        let store: Store<String, u32> = Store::with_options("test", options.clone().tag("count"))?;
+
+        assert_eq!(store.manifest().tag.as_deref(), Some("count"));
+
+        let mismatch = |result: Result<()>| match result
+        {
+            Err(StoreError::Mismatch { what, .. }) => what,
+            _ => "",
+        };
+
+        assert_eq!(
+            mismatch(
+                Store::<String, u64>::with_options("test", options.clone().tag("total")).map(drop)
+            ),
+            "tag"
+        );
+        assert_eq!(
+            mismatch(Store::<String, u32>::with_options("test", options.clone()).map(drop)),
+            ""
+        );
+        assert_eq!(
+            mismatch(
+                Store::<String, u32, _>::with_options("test", options.clone().format(PrettyJson))
+                    .map(drop)
+            ),
+            "format"
+        );
+        assert_eq!(
+            mismatch(
+                Store::<String, u32>::with_options("test", options.clone().schema_version(1))
+                    .map(drop)
+            ),
+            "schema version"
+        );
+
+        let store: Store<String, u32> =
+            Store::with_options("test", options.clone().schema_version(3))?;
+
+        assert_eq!(store.manifest().schema_version, 3);
+        assert!(Store::<String, u32>::with_options("test", options).is_err());
+
+        Ok(())
+    }
+
+    /// Test that a read-only store checks its manifest without writing it.
+    #[test]
+    fn test_read_only_manifest() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let manifest = Path::new("legacy/.store.json");
+
+        backend.create_dir_all(Path::new("legacy"))?;
+        backend.write_atomic(Path::new("legacy/a.json"), b"1", Durability::None)?;
+
+        let (store, report) =
+            Store::<String, u32>::open_with("legacy", options.clone().read_only())?;
+
+        assert!(report.is_clean());
+        assert_eq!(store.all(), vec![&1]);
+        assert!(!backend.exists(manifest));
+
+        drop(store);
+        Store::<String, u32>::with_options("legacy", options.clone())?;
+
+        let upgraded = options
+            .clone()
+            .schema_version(1)
+            .migrations(Migrations::new().step(0, |value| value))
+            .read_only();
+        let (store, _) = Store::<String, u32>::open_with("legacy", upgraded)?;
+
+        assert_eq!(store.manifest().schema_version, 1);
+        assert_eq!(store.all(), vec![&1]);
+        assert!(String::from_utf8_lossy(&backend.read(manifest)?).contains("\"schema_version\": 0"));
+        assert!(matches!(
+            Store::<String, u32, _>::with_options("legacy", options.format(PrettyJson).read_only()),
+            Err(StoreError::Mismatch { what: "format", .. })
+        ));
+
+        Ok(())
+    }
+
+    /// Test that old values are migrated on load and rewritten when eager.
+    #[test]
+    fn test_migration() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let mut store: Store<String, u32> = Store::with_options("test", options.clone())?;
+
+        store.save("a", 1)?;
+        store.save("b", 2)?;
+
+        let migrations =
+            Migrations::new().step(0, |value| (value.as_u64().unwrap_or(0) * 10).into());
+        let options = options.schema_version(1).migrations(migrations);
+
+        let (store, report) = Store::<String, u32>::open_with("test", options.clone())?;
+
+        assert!(report.is_clean());
+        assert_eq!(store.all(), vec![&10, &20]);
+        assert_eq!(backend.read(Path::new("test/a.json"))?, b"1");
+
+        let (_, report) = Store::<String, u32>::open_with("test", options.migrate_eagerly(true))?;
+
+        assert!(report.is_clean());
+        assert_eq!(
+            backend.read(Path::new("test/a.json"))?,
+            br#"{"$version":1,"$data":10}"#
+        );
+
+        Ok(())
+    }
+
+    /// Test that conditional saves fail once another store wrote the entry.
+    #[test]
+    fn test_versions() -> Result<()>
+    {
+        let options = memory();
+        let mut ours: Store<String, u32> = Store::with_options("test", options.clone())?;
+
+        let version = ours.save_if_version("a", 1, None)?;
+
+        assert_eq!(ours.get_versioned("a"), Some((&1, version)));
+        assert!(matches!(
+            ours.save_if_version("a", 1, None),
+            Err(StoreError::Conflict { found: Some(found), .. }) if found == version
+        ));
+
+        let (mut theirs, _) = Store::<String, u32>::open_with("test", options)?;
+
+        assert_eq!(theirs.get_versioned("a"), Some((&1, version)));
+
+        let moved = theirs.save_if_version("a", 2, Some(version))?;
+
+        assert_ne!(moved, version);
+        assert!(matches!(
+            ours.save_if_version("a", 3, Some(version)),
+            Err(StoreError::Conflict { found: Some(found), .. }) if found == moved
+        ));
+        assert_eq!(ours.get("a"), Some(&1));
+        assert!(matches!(
+            ours.compare_and_swap("a", Some(&1), 3),
+            Err(StoreError::Conflict { .. })
+        ));
+
+        ours.compare_and_swap("a", Some(&2), 3)?;
+        theirs.delete("a")?;
+
+        assert!(matches!(
+            ours.save_if_version("a", 4, ours.get_versioned("a").map(|(_, version)| version)),
+            Err(StoreError::Conflict { found: None, .. })
+        ));
+
+        ours.compare_and_swap("a", None, 4)?;
+
+        assert_eq!(ours.get("a"), Some(&4));
+
         Ok(())
     }
 }
@@ -1,29 +1,72 @@
-use anyhow::Result;
+use super::{
+    error::{Result, StoreError},
+    lock::{self, StoreLock},
+    migration, Backend, ConflictPolicy, Durability, Fingerprint, Format, Json, Migrations, Options,
+    RefMut, Version,
+};
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::{self, File},
+    mem,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
+/// The state of a stored value's file compared to its fingerprint.
+enum Disk
+{
+    /// The file did not change.
+    Unchanged,
+    /// The file was deleted.
+    Missing,
+    /// The file was changed, and now has these contents.
+    Changed(Vec<u8>),
+}
+
 /// A stored value.
 ///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use store::Stored;
 ///
-/// let mut stored = Stored::new("data", "Hello, world!")?;
+/// # fn main() -> store::Result<()> {
+/// let mut stored = Stored::new("data", String::from("Hello, world!"))?;
 ///
 /// stored.save()?;
+/// # Ok(())
+/// # }
 /// ```
-pub struct Stored<T>
+pub struct Stored<T, F = Json>
 where
     for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
 {
     /// The path to the stored value.
     pub(super) path: PathBuf,
     /// The stored value.
     pub(super) value: T,
+    /// The format the value is stored in.
+    pub(super) format: F,
+    /// How durable saves of the value are.
+    pub(super) durability: Durability,
+    /// The storage the value is read from and written to.
+    pub(super) backend: Arc<dyn Backend>,
+    /// When the value was added to its store, used for insertion order.
+    pub(super) sequence: u64,
+    /// The schema version the value is written with.
+    pub(super) schema_version: u32,
+    /// The steps migrating values from older schema versions.
+    pub(super) migrations: Migrations,
+    /// What the file looked like when it was last read or written, or `None`
+    /// if it was neither.
+    pub(super) fingerprint: Option<Fingerprint>,
+    /// Whether the value was changed in memory since the file was last read
+    /// or written.
+    pub(super) modified: bool,
+    /// What saving does when the file was changed by someone else.
+    pub(super) policy: ConflictPolicy<T>,
+    /// The lock of the store the value belongs to, held while writing.
+    pub(super) store_lock: Option<Arc<StoreLock>>,
 }
 
 impl<T> Stored<T>
@@ -31,37 +74,348 @@ where
     for<'de> T: Serialize + Deserialize<'de>,
 {
     /// Create a new stored value.
+    ///
+    /// If the file already exists its value is loaded, otherwise `default` is
+    /// used. Nothing is written until the value is saved.
     pub fn new<P>(path: P, default: T) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let path = path.as_ref().with_extension("json");
-        let file = File::create(&path)?;
-        let value: T = serde_json::from_reader(file).unwrap_or(default);
+        Self::load_or_else(path, || default)
+    }
+
+    /// Open an existing stored value.
+    ///
+    /// Returns `None` when the file does not exist, and an error when it
+    /// exists but cannot be read or deserialized. The file is never written.
+    pub fn open<P>(path: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_with(path, &Options::new())
+    }
+
+    /// Load an existing stored value, failing if the file does not exist.
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::load_with(path, &Options::new())
+    }
 
-        Ok(Self { path, value })
+    /// Load an existing stored value, using the default value if the file
+    /// does not exist.
+    pub fn load_or_default<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        T: Default,
+    {
+        Self::load_or_else(path, T::default)
+    }
+
+    /// Load an existing stored value, computing a value if the file does not
+    /// exist.
+    pub fn load_or_else<P, D>(path: P, default: D) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        D: FnOnce() -> T,
+    {
+        Self::load_or_else_with(path, &Options::new(), default)
+    }
+
+    /// Create a new stored value and write it, failing with
+    /// [`StoreError::AlreadyExists`] if the file already exists.
+    pub fn create_new<P>(path: P, value: T) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::create_new_with(path, &Options::new(), value)
+    }
+}
+
+impl<T, F> Stored<T, F>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Open an existing stored value using the given options.
+    ///
+    /// See [`Stored::open`].
+    pub fn open_with<P>(path: P, options: &Options<F>) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().with_extension(options.format.extension());
+
+        let (bytes, fingerprint) = match read(&*options.backend, &path)
+        {
+            Ok(read) => read,
+            Err(StoreError::NotFound { .. }) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        let (value, migrated) = migration::decode(&path, &bytes, options)?;
+        let mut stored = Self::with_value(path, value, options);
+
+        stored.fingerprint = Some(fingerprint);
+
+        if migrated && options.migrate_eagerly
+        {
+            stored.save()?;
+        }
+
+        Ok(Some(stored))
+    }
+
+    /// Load an existing stored value using the given options.
+    ///
+    /// See [`Stored::load`].
+    pub fn load_with<P>(path: P, options: &Options<F>) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        Self::open_with(path, options)?.ok_or_else(|| StoreError::NotFound {
+            path: path.with_extension(options.format.extension()),
+        })
+    }
+
+    /// Load an existing stored value using the given options, computing a
+    /// value if the file does not exist.
+    ///
+    /// See [`Stored::load_or_else`].
+    pub fn load_or_else_with<P, D>(path: P, options: &Options<F>, default: D) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        D: FnOnce() -> T,
+    {
+        let path = path.as_ref();
+
+        match Self::open_with(path, options)?
+        {
+            Some(stored) => Ok(stored),
+            None => Ok(Self::with_value(
+                path.with_extension(options.format.extension()),
+                default(),
+                options,
+            )),
+        }
+    }
+
+    /// Create a new stored value using the given options.
+    ///
+    /// See [`Stored::create_new`].
+    pub fn create_new_with<P>(path: P, options: &Options<F>, value: T) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().with_extension(options.format.extension());
+        let mut stored = Self::with_value(path, value, options);
+
+        stored.create()?;
+
+        Ok(stored)
+    }
+
+    /// Create a stored value from an already known value, without touching
+    /// the file.
+    pub(super) fn with_value(path: PathBuf, value: T, options: &Options<F>) -> Self
+    {
+        Self {
+            path,
+            value,
+            format: options.format.clone(),
+            durability: options.durability,
+            backend: options.backend.clone(),
+            sequence: 0,
+            schema_version: options.schema_version,
+            migrations: options.migrations.clone(),
+            fingerprint: None,
+            modified: false,
+            policy: ConflictPolicy::default(),
+            store_lock: options.store_lock.clone(),
+        }
+    }
+
+    /// Set how durable saves of the value are.
+    pub fn with_durability(mut self, durability: Durability) -> Self
+    {
+        self.durability = durability;
+        self
+    }
+
+    /// Change how durable saves of the value are.
+    pub fn set_durability(&mut self, durability: Durability)
+    {
+        self.durability = durability;
+    }
+
+    /// Get how durable saves of the value are.
+    pub fn durability(&self) -> Durability
+    {
+        self.durability
+    }
+
+    /// Set what saving does when the file was changed by someone else.
+    pub fn with_conflict_policy(mut self, policy: ConflictPolicy<T>) -> Self
+    {
+        self.policy = policy;
+        self
+    }
+
+    /// Change what saving does when the file was changed by someone else.
+    pub fn set_conflict_policy(&mut self, policy: ConflictPolicy<T>)
+    {
+        self.policy = policy;
+    }
+
+    /// Get what saving does when the file was changed by someone else.
+    /// Defaults to [`ConflictPolicy::Overwrite`].
+    pub fn conflict_policy(&self) -> &ConflictPolicy<T>
+    {
+        &self.policy
     }
 
     /// Save the stored value.
-    pub fn save(&self) -> Result<()>
+    ///
+    /// The value is written to a temporary file which then replaces the
+    /// existing file, so a crash never leaves a partially written file behind.
+    /// If the file was changed by someone else the conflict policy decides
+    /// what happens, see [`ConflictPolicy`].
+    pub fn save(&mut self) -> Result<()>
     {
-        let file = File::create(&self.path)?;
-        serde_json::to_writer(file, &self.value)?;
+        let _held = lock::write(self.store_lock.as_ref())?;
+
+        if let (Some(theirs), ConflictPolicy::Merge(merge)) = (self.resolve()?, &self.policy)
+        {
+            merge(&mut self.value, theirs);
+            self.modified = true;
+        }
+
+        let bytes = self.to_bytes()?;
+
+        self.backend
+            .write_atomic(&self.path, &bytes, self.durability)?;
+        self.written(&bytes);
+
+        Ok(())
+    }
+
+    /// Write the stored value to a new file, failing with
+    /// [`StoreError::AlreadyExists`] if the file already exists.
+    pub(super) fn create(&mut self) -> Result<()>
+    {
+        let bytes = self.to_bytes()?;
+        let _held = lock::write(self.store_lock.as_ref())?;
+
+        self.backend
+            .write_new(&self.path, &bytes, self.durability)?;
+        self.written(&bytes);
+
         Ok(())
     }
 
     /// Store a new value.
     pub fn store(&mut self, value: T) -> Result<()>
     {
-        self.value = value;
-        self.save()?;
+        self.replace(value)?;
         Ok(())
     }
 
+    /// Store a new value, returning the previous one.
+    ///
+    /// The stored value is only changed once the new value has been written,
+    /// so on error the previous value is kept. If the file was changed by
+    /// someone else the conflict policy decides what happens, see
+    /// [`ConflictPolicy`].
+    pub fn replace(&mut self, mut value: T) -> Result<T>
+    {
+        let _held = lock::write(self.store_lock.as_ref())?;
+
+        if let (Some(theirs), ConflictPolicy::Merge(merge)) = (self.resolve()?, &self.policy)
+        {
+            merge(&mut value, theirs);
+        }
+
+        let bytes = self.serialize(&value)?;
+
+        self.backend
+            .write_atomic(&self.path, &bytes, self.durability)?;
+        self.written(&bytes);
+
+        Ok(mem::replace(&mut self.value, value))
+    }
+
+    /// Modify the stored value in place and save it.
+    ///
+    /// If saving fails the modification is kept in memory, so a later save
+    /// can retry the write.
+    pub fn modify<M, R>(&mut self, f: M) -> Result<R>
+    where
+        M: FnOnce(&mut T) -> R,
+    {
+        let result = f(&mut self.value);
+
+        self.modified = true;
+        self.save()?;
+        Ok(result)
+    }
+
+    /// Get a mutable reference to the stored value that saves it when
+    /// dropped.
+    pub fn get_mut(&mut self) -> RefMut<'_, T, F>
+    {
+        RefMut::new(self)
+    }
+
     /// Delete the file.
     pub fn delete(&self) -> Result<()>
     {
-        fs::remove_file(&self.path)?;
+        let _held = lock::write(self.store_lock.as_ref())?;
+
+        self.backend.remove(&self.path)
+    }
+
+    /// Get the version of the file as it was last read or written, or `None`
+    /// if it was neither.
+    pub fn version(&self) -> Option<Version>
+    {
+        self.fingerprint.map(|fingerprint| fingerprint.version)
+    }
+
+    /// Get what the file looked like when it was last read or written, or
+    /// `None` if it was neither.
+    pub fn fingerprint(&self) -> Option<Fingerprint>
+    {
+        self.fingerprint
+    }
+
+    /// Check whether the file was changed, created or deleted by someone else
+    /// since it was last read or written.
+    ///
+    /// The file is only read if its size or modification time changed, so a
+    /// change keeping both, within the resolution of the modification time,
+    /// goes unnoticed.
+    pub fn is_stale(&self) -> Result<bool>
+    {
+        Ok(!matches!(self.check()?, Disk::Unchanged))
+    }
+
+    /// Replace the stored value with the one currently in the file.
+    ///
+    /// Fails with [`StoreError::NotFound`] if the file does not exist, in
+    /// which case the stored value is kept.
+    pub fn reload(&mut self) -> Result<()>
+    {
+        let _held = lock::read(self.store_lock.as_ref())?;
+        let (bytes, fingerprint) = read(&*self.backend, &self.path)?;
+        let (value, _) = migration::decode(&self.path, &bytes, &self.options())?;
+
+        self.value = value;
+        self.fingerprint = Some(fingerprint);
+        self.modified = false;
+
         Ok(())
     }
 
@@ -70,4 +424,282 @@ where
     {
         &self.value
     }
+
+    /// Convert into the stored value.
+    pub fn into_value(self) -> T
+    {
+        self.value
+    }
+
+    /// Serialize the stored value.
+    fn to_bytes(&self) -> Result<Vec<u8>>
+    {
+        self.serialize(&self.value)
+    }
+
+    /// Remember the contents just written to the file.
+    fn written(&mut self, bytes: &[u8])
+    {
+        self.fingerprint = Some(Fingerprint::of(&*self.backend, &self.path, bytes));
+        self.modified = false;
+    }
+
+    /// Compare the file to its fingerprint.
+    fn check(&self) -> Result<Disk>
+    {
+        let metadata = match self.backend.metadata(&self.path)
+        {
+            Ok(metadata) => metadata,
+            Err(StoreError::NotFound { .. }) => match self.fingerprint
+            {
+                Some(_) => return Ok(Disk::Missing),
+                None => return Ok(Disk::Unchanged),
+            },
+            Err(error) => return Err(error),
+        };
+
+        if let Some(fingerprint) = self.fingerprint
+        {
+            if metadata.len == fingerprint.len && metadata.modified == fingerprint.modified
+            {
+                return Ok(Disk::Unchanged);
+            }
+        }
+
+        let bytes = match self.backend.read(&self.path)
+        {
+            Ok(bytes) => bytes,
+            Err(StoreError::NotFound { .. }) => return Ok(Disk::Missing),
+            Err(error) => return Err(error),
+        };
+
+        match self.fingerprint
+        {
+            Some(fingerprint) if fingerprint.version == Version::of(&bytes) => Ok(Disk::Unchanged),
+            _ => Ok(Disk::Changed(bytes)),
+        }
+    }
+
+    /// Apply the conflict policy before the file is overwritten.
+    ///
+    /// Returns the value in the file if it must be merged into the value
+    /// being saved.
+    fn resolve(&self) -> Result<Option<T>>
+    {
+        if let ConflictPolicy::Overwrite = self.policy
+        {
+            return Ok(None);
+        }
+
+        let bytes = match self.check()?
+        {
+            Disk::Unchanged => return Ok(None),
+            Disk::Missing if matches!(self.policy, ConflictPolicy::Merge(_)) => return Ok(None),
+            Disk::Missing => None,
+            Disk::Changed(bytes) => Some(bytes),
+        };
+
+        match (&self.policy, bytes)
+        {
+            (ConflictPolicy::Merge(_), Some(bytes)) =>
+            {
+                let (theirs, _) = migration::decode(&self.path, &bytes, &self.options())?;
+
+                Ok(Some(theirs))
+            }
+            (_, bytes) => Err(StoreError::Conflict {
+                path: self.path.clone(),
+                found: bytes.map(|bytes| Version::of(&bytes)),
+            }),
+        }
+    }
+
+    /// Get the options the stored value was opened with, as far as they
+    /// affect reading it.
+    fn options(&self) -> Options<F>
+    {
+        Options {
+            format: self.format.clone(),
+            durability: self.durability,
+            backend: self.backend.clone(),
+            schema_version: self.schema_version,
+            tag: None,
+            migrations: self.migrations.clone(),
+            migrate_eagerly: false,
+            read_only: false,
+            lock_timeout: None,
+            store_lock: self.store_lock.clone(),
+        }
+    }
+
+    /// Serialize a value in the format of the stored value.
+    pub(super) fn serialize(&self, value: &T) -> Result<Vec<u8>>
+    {
+        migration::encode(&self.format, self.schema_version, value)
+            .map_err(|error| StoreError::serialize(&self.path, error))
+    }
+}
+
+/// Read a file along with its fingerprint.
+///
+/// The file is stat'ed before it is read, so a write in between makes the
+/// fingerprint look stale rather than hiding the write.
+fn read(backend: &dyn Backend, path: &Path) -> Result<(Vec<u8>, Fingerprint)>
+{
+    let modified = backend.metadata(path)?.modified;
+    let bytes = backend.read(path)?;
+    let fingerprint = Fingerprint::with_modified(modified, &bytes);
+
+    Ok((bytes, fingerprint))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::MemoryBackend;
+
+    /// Test that opening distinguishes missing files from corrupt ones and never writes.
+    #[test]
+    fn test_open() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let corrupt = Path::new("corrupt.json");
+
+        assert!(Stored::<String>::open_with("missing", &options)?.is_none());
+        assert!(!backend.exists(Path::new("missing.json")));
+
+        backend.write_atomic(corrupt, b"{ not json", Durability::None)?;
+
+        assert!(matches!(
+            Stored::<String>::open_with("corrupt", &options),
+            Err(StoreError::Corrupt { line: 1, .. })
+        ));
+        assert!(Stored::load_or_else_with("corrupt", &options, String::new).is_err());
+        assert_eq!(backend.read(corrupt)?, b"{ not json");
+
+        Ok(())
+    }
+
+    /// Test that loading keeps the existing value and create_new refuses to overwrite it.
+    #[test]
+    fn test_load() -> Result<()>
+    {
+        let options = Options::new().backend(MemoryBackend::new());
+
+        Stored::create_new_with("hello", &options, String::from("world"))?;
+
+        assert!(matches!(
+            Stored::create_new_with("hello", &options, String::from("other")),
+            Err(StoreError::AlreadyExists { .. })
+        ));
+        assert_eq!(
+            Stored::<String>::load_with("hello", &options)?.value(),
+            "world"
+        );
+        assert_eq!(
+            Stored::load_or_else_with("hello", &options, || String::from("default"))?.value(),
+            "world"
+        );
+        assert_eq!(
+            Stored::<String>::load_or_else_with("missing", &options, String::new)?.value(),
+            ""
+        );
+        assert!(matches!(
+            Stored::<String>::load_with("missing", &options),
+            Err(StoreError::NotFound { .. })
+        ));
+
+        Ok(())
+    }
+
+    /// Test that modifications are saved, and guards only save when mutated.
+    #[test]
+    fn test_modify() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let path = Path::new("counter.json");
+        let mut stored = Stored::load_or_else_with("counter", &options, || 0)?;
+
+        assert_eq!(
+            stored.modify(|value| {
+                *value += 1;
+                *value
+            })?,
+            1
+        );
+        assert_eq!(backend.read(path)?, b"1");
+
+        let guard = stored.get_mut();
+        assert_eq!(*guard, 1);
+        drop(guard);
+
+        backend.remove(path)?;
+        assert!(!backend.exists(path));
+
+        let mut guard = stored.get_mut();
+        *guard += 1;
+        assert!(guard.is_dirty());
+        guard.commit()?;
+
+        assert_eq!(backend.read(path)?, b"2");
+
+        Ok(())
+    }
+
+    /// Test that external changes are detected, and handled as the conflict
+    /// policy says.
+    #[test]
+    fn test_stale() -> Result<()>
+    {
+        let backend = MemoryBackend::new();
+        let options = Options::new().backend(backend.clone());
+        let path = Path::new("list.json");
+        let mut stored = Stored::load_or_else_with("list", &options, Vec::<u32>::new)?;
+
+        stored.replace(vec![1])?;
+        assert!(!stored.is_stale()?);
+
+        backend.write_atomic(path, b"[1,2]", Durability::default())?;
+        assert!(stored.is_stale()?);
+
+        stored.reload()?;
+        assert_eq!(stored.value(), &vec![1, 2]);
+        assert!(!stored.is_stale()?);
+
+        backend.write_atomic(path, b"[3]", Durability::default())?;
+        stored.set_conflict_policy(ConflictPolicy::Fail);
+
+        assert!(matches!(
+            stored.replace(vec![4]),
+            Err(StoreError::Conflict { found: Some(found), .. }) if found == Version::of(b"[3]")
+        ));
+        assert_eq!(backend.read(path)?, b"[3]");
+
+        stored.set_conflict_policy(ConflictPolicy::merge(|ours: &mut Vec<u32>, theirs| {
+            ours.extend(theirs)
+        }));
+        stored.replace(vec![4])?;
+
+        assert_eq!(stored.value(), &vec![4, 3]);
+        assert_eq!(backend.read(path)?, b"[4,3]");
+
+        backend.remove(path)?;
+        assert!(stored.is_stale()?);
+        assert!(matches!(stored.reload(), Err(StoreError::NotFound { .. })));
+
+        stored.set_conflict_policy(ConflictPolicy::Fail);
+        assert!(matches!(
+            stored.save(),
+            Err(StoreError::Conflict { found: None, .. })
+        ));
+
+        stored.set_conflict_policy(ConflictPolicy::Overwrite);
+        stored.save()?;
+        assert_eq!(backend.read(path)?, b"[4,3]");
+
+        Ok(())
+    }
 }
@@ -0,0 +1,200 @@
+use super::{
+    batch::Batch,
+    error::{Result, StoreError},
+    key::StoreKey,
+    Format, Json, Store,
+};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Borrow, collections::BTreeMap};
+
+/// A view of a store inside [`Store::transaction`].
+///
+/// Saves and deletes are kept in memory and seen by every later read in the
+/// same transaction. They are only written, as a single unit through the same
+/// intent log as a [`Batch`], once the transaction returns `Ok`.
+///
+/// # Example
+///
+/// ```no_run
+/// use store::{Store, StoreError};
+///
+/// # fn main() -> store::Result<()> {
+/// let mut store: Store<String, u64> = Store::new("accounts")?;
+///
+/// store.transaction(|tx| {
+///     let alice = tx.get("alice").copied().unwrap_or(0);
+///     let bob = tx.get("bob").copied().unwrap_or(0);
+///
+///     if alice < 10
+///     {
+///         return Err(StoreError::Other("insufficient funds".into()));
+///     }
+///
+///     tx.save("alice", alice - 10)?;
+///     tx.save("bob", bob + 10)?;
+///
+///     Ok(())
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Transaction<'a, K, T, F = Json>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// The store the transaction reads from.
+    store: &'a Store<K, T, F>,
+    /// The new value of every changed key, or `None` if it was deleted.
+    changes: BTreeMap<K, Option<T>>,
+}
+
+impl<'a, K, T, F> Transaction<'a, K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Get data from the store, as changed by the transaction so far.
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match self.changes.get(key)
+        {
+            Some(value) => value.as_ref(),
+            None => self.store.get(key),
+        }
+    }
+
+    /// Check whether the store has an entry with the given key, as changed by
+    /// the transaction so far.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Save data when the transaction commits.
+    ///
+    /// Fails with [`StoreError::InvalidKey`] if the key cannot be encoded.
+    pub fn save<Q>(&mut self, key: Q, value: T) -> Result<()>
+    where
+        Q: Into<K>,
+    {
+        let key = key.into();
+
+        self.store.path_for(&key)?;
+        self.changes.insert(key, Some(value));
+
+        Ok(())
+    }
+
+    /// Delete data when the transaction commits.
+    ///
+    /// Fails with [`StoreError::NotFound`] if the entry does not exist, as
+    /// changed by the transaction so far.
+    pub fn delete<Q>(&mut self, key: Q) -> Result<()>
+    where
+        Q: Into<K>,
+    {
+        let key = key.into();
+        let path = self.store.path_for(&key)?;
+
+        if !self.contains_key(&key)
+        {
+            return Err(StoreError::NotFound { path });
+        }
+
+        self.changes.insert(key, None);
+
+        Ok(())
+    }
+}
+
+impl<K, T, F> Store<K, T, F>
+where
+    K: StoreKey,
+    for<'de> T: Serialize + Deserialize<'de>,
+    F: Format,
+{
+    /// Run `f` as a transaction, see [`Transaction`].
+    ///
+    /// If `f` returns `Err` or panics, none of its changes are written and
+    /// the store is left untouched.
+    pub fn transaction<R, X>(&mut self, f: X) -> Result<R>
+    where
+        X: FnOnce(&mut Transaction<'_, K, T, F>) -> Result<R>,
+    {
+        let mut transaction = Transaction {
+            store: self,
+            changes: BTreeMap::new(),
+        };
+
+        let result = f(&mut transaction)?;
+        let changes = transaction.changes;
+
+        Batch::new(self, changes).commit()?;
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{MemoryBackend, Options};
+    use std::panic::{self, AssertUnwindSafe};
+
+    /// Test that a transaction reads its own writes and only commits on `Ok`.
+    #[test]
+    fn test_transaction() -> Result<()>
+    {
+        let options = Options::new().backend(MemoryBackend::new());
+        let mut store: Store<String, u32> = Store::with_options("test", options)?;
+
+        store.save("a", 1)?;
+
+        let sum = store.transaction(|tx| {
+            tx.save("b", 2)?;
+            tx.delete("a")?;
+
+            assert_eq!(tx.get("a"), None);
+            assert!(tx.delete("a").is_err());
+
+            Ok(tx.get("b").copied().unwrap_or(0) + 1)
+        })?;
+
+        assert_eq!(sum, 3);
+        assert_eq!(store.keys().collect::<Vec<_>>(), vec!["b"]);
+
+        let result = store.transaction(|tx| {
+            tx.save("c", 3)?;
+            Err::<(), _>(StoreError::Other("rollback".into()))
+        });
+
+        assert!(result.is_err());
+        assert!(!store.contains_key("c"));
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            store.transaction(|tx| -> Result<()> {
+                tx.save("d", 4)?;
+                panic!("rollback");
+            })
+        }));
+
+        assert!(result.is_err());
+        assert!(!store.contains_key("d"));
+
+        let (store, _) = Store::<String, u32>::open_with("test", store.options.clone())?;
+
+        assert_eq!(store.keys().collect::<Vec<_>>(), vec!["b"]);
+
+        Ok(())
+    }
+}
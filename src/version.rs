@@ -0,0 +1,80 @@
+use super::Backend;
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+    time::SystemTime,
+};
+
+/// The version of an entry, a hash of its file's contents.
+///
+/// Any change to the file gives a new version, so comparing the version an
+/// entry was read with to the one on disk tells whether someone else wrote it
+/// in the meantime. Versions are only meaningful when compared for equality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Version(u64);
+
+impl Version
+{
+    /// Get the version of a file's contents, using 64-bit FNV-1a.
+    pub(crate) fn of(bytes: &[u8]) -> Self
+    {
+        let hash = bytes
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash: u64, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            });
+
+        Self(hash)
+    }
+}
+
+impl Display for Version
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// What a file looked like when it was last read or written.
+///
+/// The size and modification time tell cheaply whether the file may have
+/// changed, and the version whether its contents actually did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint
+{
+    /// The size of the file in bytes.
+    pub len: u64,
+    /// When the file was last modified.
+    pub modified: SystemTime,
+    /// The version of the file's contents.
+    pub version: Version,
+}
+
+impl Fingerprint
+{
+    /// Get the fingerprint of a file with the given contents, using its
+    /// modification time as found by `backend`.
+    ///
+    /// When the modification time cannot be read the Unix epoch is used, so
+    /// the file is hashed again the next time it is checked.
+    pub(crate) fn of(backend: &dyn Backend, path: &Path, bytes: &[u8]) -> Self
+    {
+        let modified = backend
+            .metadata(path)
+            .map_or(SystemTime::UNIX_EPOCH, |metadata| metadata.modified);
+
+        Self::with_modified(modified, bytes)
+    }
+
+    /// Get the fingerprint of a file with the given contents and
+    /// modification time.
+    pub(crate) fn with_modified(modified: SystemTime, bytes: &[u8]) -> Self
+    {
+        Self {
+            len: bytes.len() as u64,
+            modified,
+            version: Version::of(bytes),
+        }
+    }
+}